@@ -0,0 +1,38 @@
+use crate::canvas::Canvas;
+
+/// Floating-point frame buffer summing up the samples of every pixel over several frames.
+#[derive(Default)]
+pub struct Accumulator {
+    pub width: usize,
+    pub height: usize,
+    /// Sum of the colors of all samples of every pixel.
+    pub sum: Vec<(f64, f64, f64)>,
+    /// Number of samples per pixel accumulated so far.
+    pub samples: usize,
+}
+
+impl Accumulator {
+    /// Discards the accumulated samples, e.g. when the camera moves.
+    pub fn reset(&mut self) {
+        self.sum.fill((0., 0., 0.));
+        self.samples = 0;
+    }
+
+    /// Matches the buffer to the canvas size, discarding the samples if it changes.
+    pub fn fit(&mut self, width: usize, height: usize) {
+        if (self.width, self.height) != (width, height) {
+            self.width = width;
+            self.height = height;
+            self.sum = vec![(0., 0., 0.); width * height];
+            self.samples = 0;
+        }
+    }
+
+    /// Draws the average of the accumulated samples onto the canvas.
+    pub fn draw(&self, canvas: &mut Canvas) {
+        let n = self.samples.max(1) as f64;
+        for (pixel, sum) in canvas.frame.iter_mut().zip(self.sum.iter()) {
+            *pixel = [(sum.0 / n) as f32, (sum.1 / n) as f32, (sum.2 / n) as f32];
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::canvas::Canvas;
+use crate::cgfs_raytracing::{Attenuation, Light, Scene};
+use crate::cgfs_scene;
+use crate::cli::{Benchmark, Filter, Rasterizer, Sampling, Shading};
+use crate::pipeline;
+use crate::shaders::{StandardFragment, StandardVertex};
+use crate::tiles::TileRenderer;
+use std::time::{Duration, Instant};
+
+/// Runs the benchmark on a canvas of the given size and prints the results.
+pub fn run(benchmark: &Benchmark, width: usize, height: usize) {
+    match *benchmark {
+        Benchmark::Bvh { size } => bvh(size, width, height),
+        Benchmark::Raster { size, runs } => raster(size, runs, width, height),
+    }
+}
+
+/// Draws the frame with `draw` and measures how long it took.
+fn time_frame<Draw>(width: usize, height: usize, draw: Draw) -> (Vec<[f32; 3]>, Duration)
+where
+    Draw: FnOnce(&mut Canvas),
+{
+    let mut frame = vec![[0.; 3]; width * height];
+    let start = Instant::now();
+    draw(&mut Canvas::new(&mut frame, width, height));
+    (frame, start.elapsed())
+}
+
+/// Compares raytracing a checkerboard of cubes with and without the bounding volume hierarchy.
+fn bvh(size: i32, width: usize, height: usize) {
+    let mut scene = Scene::from_instances(&cgfs_scene::cube_checkerboard(size));
+    // a point light makes every hit cast a shadow ray as well
+    scene.lights = vec![
+        Light::Ambient {
+            intensity: 0.4,
+            color: (1., 1., 1.),
+        },
+        Light::Point {
+            intensity: 0.6,
+            color: (1., 1., 1.),
+            position: (size as f64, size as f64, 0.),
+            attenuation: Attenuation::None,
+        },
+    ];
+    println!(
+        "Raytracing {} triangles on a {width}x{height} canvas",
+        scene.objects().len()
+    );
+
+    let start = Instant::now();
+    scene.build_bvh();
+    println!("BVH built in {:.2?}", start.elapsed());
+    let (with_bvh, t_bvh) = time_frame(width, height, |canvas| {
+        crate::draw_scene_raytracing(
+            canvas,
+            0.,
+            &scene,
+            1,
+            Filter::Box,
+            &mut TileRenderer::default(),
+        )
+    });
+    println!("with BVH:    {t_bvh:.2?}");
+
+    scene.bvh = None;
+    let (without_bvh, t_brute) = time_frame(width, height, |canvas| {
+        crate::draw_scene_raytracing(
+            canvas,
+            0.,
+            &scene,
+            1,
+            Filter::Box,
+            &mut TileRenderer::default(),
+        )
+    });
+    println!("without BVH: {t_brute:.2?}");
+    println!(
+        "speedup:     {:.1}x",
+        t_brute.as_secs_f64() / t_bvh.as_secs_f64()
+    );
+
+    let different = with_bvh
+        .iter()
+        .zip(without_bvh.iter())
+        .filter(|(a, b)| a != b)
+        .count();
+    if different > 0 {
+        println!("{different} pixels differ between the two images");
+    }
+}
+
+/// Compares rasterizing a checkerboard of cubes span by span, one triangle after another,
+/// with binning the triangles into tiles drawn in parallel.
+fn raster(size: i32, runs: u32, width: usize, height: usize) {
+    let instances = cgfs_scene::cube_checkerboard(size);
+    let lights = cgfs_scene::default_lights();
+    let camera = cgfs_scene::Camera::default();
+    let vertex_shader = StandardVertex {
+        shading: Shading::Unlit,
+    };
+    let fragment_shader = StandardFragment {
+        shading: Shading::Unlit,
+        texture: None,
+        sampling: Sampling::Bilinear,
+        mipmaps: false,
+    };
+    println!(
+        "Rasterizing {} triangles on a {width}x{height} canvas with {} threads",
+        instances
+            .iter()
+            .map(|instance| instance.model.triangles.len())
+            .sum::<usize>(),
+        rayon::current_num_threads()
+    );
+
+    let runs = runs.max(1);
+    let time = |rasterizer| {
+        let mut total = Duration::ZERO;
+        let mut frame = Vec::new();
+        for _ in 0..runs {
+            let (f, t) = time_frame(width, height, |canvas| {
+                canvas.frame.fill([1.; 3]);
+                pipeline::draw(
+                    canvas,
+                    &camera,
+                    &instances,
+                    &lights,
+                    0.,
+                    rasterizer,
+                    &vertex_shader,
+                    &fragment_shader,
+                )
+            });
+            total += t;
+            frame = f;
+        }
+        (frame, total / runs)
+    };
+    let (spans, t_spans) = time(Rasterizer::EdgeSpans);
+    println!("spans:   {t_spans:.2?}");
+    let (tiled, t_tiled) = time(Rasterizer::EdgeTiled);
+    println!("tiled:   {t_tiled:.2?}");
+    println!(
+        "speedup: {:.1}x",
+        t_spans.as_secs_f64() / t_tiled.as_secs_f64()
+    );
+
+    // both rasterizers follow the top-left rule, so they should draw the same pixels
+    let different = spans
+        .iter()
+        .zip(tiled.iter())
+        .filter(|(a, b)| a != b)
+        .count();
+    if different > 0 {
+        println!("{different} pixels differ between the two images");
+    }
+}
@@ -0,0 +1,365 @@
+/// Number of bins used to evaluate the surface area heuristic when splitting a node.
+const BINS: usize = 12;
+/// Nodes with at most this many primitives are not split any further.
+const LEAF_SIZE: usize = 4;
+
+/// Returns the coordinate of a point along the given axis.
+fn component(v: &(f64, f64, f64), axis: usize) -> f64 {
+    match axis {
+        0 => v.0,
+        1 => v.1,
+        _ => v.2,
+    }
+}
+
+/// Checks whether a hit of primitive `i` at distance `t` replaces the closest hit so far.
+/// On a tie the primitive that comes first is kept, so the result does not depend on the traversal order.
+fn is_closer(i: usize, t: f64, closest: Option<(usize, f64)>) -> bool {
+    !matches!(closest, Some((j, t_closest)) if t == t_closest && j < i)
+}
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: (f64, f64, f64),
+    pub max: (f64, f64, f64),
+}
+
+impl Aabb {
+    /// A box containing nothing, the identity of `union`.
+    pub const EMPTY: Aabb = Aabb {
+        min: (f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        max: (f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+    };
+
+    /// The smallest box containing all the points.
+    pub fn around(points: &[(f64, f64, f64)]) -> Aabb {
+        points
+            .iter()
+            .fold(Aabb::EMPTY, |b, p| b.union(&Aabb { min: *p, max: *p }))
+    }
+
+    /// The box grown by `margin` in every direction.
+    pub fn padded(&self, margin: f64) -> Aabb {
+        Aabb {
+            min: (
+                self.min.0 - margin,
+                self.min.1 - margin,
+                self.min.2 - margin,
+            ),
+            max: (
+                self.max.0 + margin,
+                self.max.1 + margin,
+                self.max.2 + margin,
+            ),
+        }
+    }
+
+    /// The smallest box containing both boxes.
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: (
+                self.min.0.min(other.min.0),
+                self.min.1.min(other.min.1),
+                self.min.2.min(other.min.2),
+            ),
+            max: (
+                self.max.0.max(other.max.0),
+                self.max.1.max(other.max.1),
+                self.max.2.max(other.max.2),
+            ),
+        }
+    }
+
+    fn centroid(&self) -> (f64, f64, f64) {
+        (
+            (self.min.0 + self.max.0) / 2.,
+            (self.min.1 + self.max.1) / 2.,
+            (self.min.2 + self.max.2) / 2.,
+        )
+    }
+
+    fn surface_area(&self) -> f64 {
+        let e = (
+            self.max.0 - self.min.0,
+            self.max.1 - self.min.1,
+            self.max.2 - self.min.2,
+        );
+        if e.0 < 0. || e.1 < 0. || e.2 < 0. {
+            0.
+        } else {
+            2. * (e.0 * e.1 + e.1 * e.2 + e.2 * e.0)
+        }
+    }
+
+    /// Calculate where the ray enters the box using the slab method,
+    /// `None` if it misses the box within [t_min, t_max].
+    /// Takes the inverse of the ray's direction, so that it is only calculated once per ray.
+    fn intersect(
+        &self,
+        o: &(f64, f64, f64),
+        d_inv: &(f64, f64, f64),
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<f64> {
+        let mut t_near = t_min;
+        let mut t_far = t_max;
+        for axis in 0..3 {
+            let o = component(o, axis);
+            let d_inv = component(d_inv, axis);
+            let t0 = (component(&self.min, axis) - o) * d_inv;
+            let t1 = (component(&self.max, axis) - o) * d_inv;
+            if t0.is_nan() || t1.is_nan() {
+                // the ray is parallel to the slab and lies in its boundary
+                continue;
+            }
+            t_near = t_near.max(t0.min(t1));
+            t_far = t_far.min(t0.max(t1));
+        }
+        if t_near <= t_far {
+            Some(t_near)
+        } else {
+            None
+        }
+    }
+}
+
+/// A node of the hierarchy.
+/// Leaves hold `count` primitives starting at `first` in the index list,
+/// inner nodes have `count` set to 0 and their children stored at `first` and `first + 1`.
+struct Node {
+    bounds: Aabb,
+    first: usize,
+    count: usize,
+}
+
+/// Bounding volume hierarchy over a list of primitives, built using the binned surface area heuristic.
+/// It only stores the indices of the primitives, the intersection tests are left to the caller.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+    /// Primitives without a bounding box, tested against every ray.
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    /// Builds the hierarchy given the bounding box of every primitive, `None` for unbounded ones.
+    pub fn new(bounds: &[Option<Aabb>]) -> Bvh {
+        let (bounded, unbounded): (Vec<_>, Vec<_>) =
+            (0..bounds.len()).partition(|&i| bounds[i].is_some());
+        let bounds = bounds
+            .iter()
+            .map(|b| b.unwrap_or(Aabb::EMPTY))
+            .collect::<Vec<_>>();
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * bounded.len()),
+            indices: bounded,
+            unbounded,
+        };
+        if !bvh.indices.is_empty() {
+            bvh.nodes.push(Node {
+                bounds: Aabb::EMPTY,
+                first: 0,
+                count: bvh.indices.len(),
+            });
+            bvh.subdivide(0, &bounds);
+        }
+        bvh
+    }
+
+    /// Calculates the node's bounds and recursively splits it while that lowers the estimated cost.
+    fn subdivide(&mut self, node: usize, bounds: &[Aabb]) {
+        let (first, count) = (self.nodes[node].first, self.nodes[node].count);
+        let primitives = &mut self.indices[first..first + count];
+        let node_bounds = primitives
+            .iter()
+            .fold(Aabb::EMPTY, |b, &i| b.union(&bounds[i]));
+        self.nodes[node].bounds = node_bounds;
+        if count <= LEAF_SIZE {
+            return;
+        }
+
+        // primitives are sorted into bins by their centroids
+        let centroid_bounds = Aabb::around(
+            &primitives
+                .iter()
+                .map(|&i| bounds[i].centroid())
+                .collect::<Vec<_>>(),
+        );
+        let bin = |i: usize, axis: usize| {
+            let lo = component(&centroid_bounds.min, axis);
+            let hi = component(&centroid_bounds.max, axis);
+            let c = component(&bounds[i].centroid(), axis);
+            (((c - lo) / (hi - lo) * BINS as f64) as usize).min(BINS - 1)
+        };
+
+        // find the split between two bins with the lowest cost
+        let mut best: Option<(usize, usize, f64)> = None;
+        for axis in 0..3 {
+            if component(&centroid_bounds.max, axis) <= component(&centroid_bounds.min, axis) {
+                continue;
+            }
+            let mut bins = [(Aabb::EMPTY, 0); BINS];
+            for &i in primitives.iter() {
+                let b = &mut bins[bin(i, axis)];
+                b.0 = b.0.union(&bounds[i]);
+                b.1 += 1;
+            }
+            // sweep from the right to get the area and count right of every split
+            let mut right = [(0., 0); BINS - 1];
+            let (mut area, mut n) = (Aabb::EMPTY, 0);
+            for k in (1..BINS).rev() {
+                area = area.union(&bins[k].0);
+                n += bins[k].1;
+                right[k - 1] = (area.surface_area(), n);
+            }
+            // and from the left to evaluate them
+            let (mut area, mut n) = (Aabb::EMPTY, 0);
+            for k in 0..BINS - 1 {
+                area = area.union(&bins[k].0);
+                n += bins[k].1;
+                if n == 0 || right[k].1 == 0 {
+                    continue;
+                }
+                let cost = n as f64 * area.surface_area() + right[k].1 as f64 * right[k].0;
+                // `is_none_or` would need Rust 1.82
+                #[allow(clippy::unnecessary_map_or)]
+                let better = best.map_or(true, |(_, _, c)| cost < c);
+                if better {
+                    best = Some((axis, k, cost));
+                }
+            }
+        }
+
+        // keep the node as a leaf if splitting it does not pay off
+        let (axis, k) = match best {
+            Some((axis, k, cost)) if cost < count as f64 * node_bounds.surface_area() => (axis, k),
+            _ => return,
+        };
+        let mut left = 0;
+        for j in 0..count {
+            if bin(primitives[j], axis) <= k {
+                primitives.swap(left, j);
+                left += 1;
+            }
+        }
+
+        let child = self.nodes.len();
+        self.nodes.push(Node {
+            bounds: Aabb::EMPTY,
+            first,
+            count: left,
+        });
+        self.nodes.push(Node {
+            bounds: Aabb::EMPTY,
+            first: first + left,
+            count: count - left,
+        });
+        self.nodes[node].first = child;
+        self.nodes[node].count = 0;
+        self.subdivide(child, bounds);
+        self.subdivide(child + 1, bounds);
+    }
+
+    /// Finds the primitive the ray hits first within [t_min, t_max].
+    /// `hit` is given the index of a primitive and the current `t_max`,
+    /// and returns the distance to the primitive if the ray hits it in range.
+    pub fn closest<Hit>(
+        &self,
+        o: &(f64, f64, f64),
+        d: &(f64, f64, f64),
+        t_min: f64,
+        t_max: f64,
+        hit: Hit,
+    ) -> Option<(usize, f64)>
+    where
+        Hit: FnMut(usize, f64) -> Option<f64>,
+    {
+        self.traverse(o, d, t_min, t_max, false, hit)
+    }
+
+    /// Checks whether the ray hits any primitive within [t_min, t_max], as used for shadows.
+    /// `hit` is the same as in `closest`.
+    pub fn any<Hit>(
+        &self,
+        o: &(f64, f64, f64),
+        d: &(f64, f64, f64),
+        t_min: f64,
+        t_max: f64,
+        hit: Hit,
+    ) -> bool
+    where
+        Hit: FnMut(usize, f64) -> Option<f64>,
+    {
+        self.traverse(o, d, t_min, t_max, true, hit).is_some()
+    }
+
+    /// Walks the nodes the ray passes through front to back,
+    /// stopping at the first hit if `any` is set.
+    fn traverse<Hit>(
+        &self,
+        o: &(f64, f64, f64),
+        d: &(f64, f64, f64),
+        t_min: f64,
+        mut t_max: f64,
+        any: bool,
+        mut hit: Hit,
+    ) -> Option<(usize, f64)>
+    where
+        Hit: FnMut(usize, f64) -> Option<f64>,
+    {
+        let mut closest = None;
+        for &i in self.unbounded.iter() {
+            if let Some(t) = hit(i, t_max) {
+                if is_closer(i, t, closest) {
+                    t_max = t;
+                    closest = Some((i, t));
+                    if any {
+                        return closest;
+                    }
+                }
+            }
+        }
+
+        let d_inv = (1. / d.0, 1. / d.1, 1. / d.2);
+        let mut stack = Vec::with_capacity(64);
+        if let Some(root) = self.nodes.first() {
+            if let Some(t) = root.bounds.intersect(o, &d_inv, t_min, t_max) {
+                stack.push((0, t));
+            }
+        }
+        while let Some((n, t_enter)) = stack.pop() {
+            // a closer hit might have been found since the node was pushed
+            if t_enter > t_max {
+                continue;
+            }
+            let node = &self.nodes[n];
+            if node.count > 0 {
+                for &i in self.indices[node.first..node.first + node.count].iter() {
+                    if let Some(t) = hit(i, t_max) {
+                        if is_closer(i, t, closest) {
+                            t_max = t;
+                            closest = Some((i, t));
+                            if any {
+                                return closest;
+                            }
+                        }
+                    }
+                }
+            } else {
+                let (l, r) = (node.first, node.first + 1);
+                let t_l = self.nodes[l].bounds.intersect(o, &d_inv, t_min, t_max);
+                let t_r = self.nodes[r].bounds.intersect(o, &d_inv, t_min, t_max);
+                // push the farther child first, so that the nearer one is visited first
+                match (t_l, t_r) {
+                    (Some(t_l), Some(t_r)) if t_l <= t_r => stack.extend([(r, t_r), (l, t_l)]),
+                    (Some(t_l), Some(t_r)) => stack.extend([(l, t_l), (r, t_r)]),
+                    (Some(t_l), None) => stack.push((l, t_l)),
+                    (None, Some(t_r)) => stack.push((r, t_r)),
+                    (None, None) => (),
+                }
+            }
+        }
+        closest
+    }
+}
@@ -0,0 +1,69 @@
+use crate::tone_mapping::srgb_decode;
+
+/// A frame buffer of floating-point RGB pixels together with its dimensions.
+/// The colors are not limited to [0, 1], tone mapping turns them into the displayed 8-bit frame.
+pub struct Canvas<'a> {
+    pub frame: &'a mut [[f32; 3]],
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Converts an 8-bit color to the floating-point color of the canvas, ignoring the alpha.
+/// With `srgb`, when the tone mapping encodes the colors with sRGB again, it is decoded to linear light first.
+pub fn from_rgba8(rgba: &[u8; 4], srgb: bool) -> [f32; 3] {
+    let channel = |c: u8| {
+        let c = c as f32 / 255.;
+        if srgb {
+            srgb_decode(c)
+        } else {
+            c
+        }
+    };
+    [channel(rgba[0]), channel(rgba[1]), channel(rgba[2])]
+}
+
+impl<'a> Canvas<'a> {
+    /// Wraps a frame buffer of `width` by `height` RGB pixels.
+    pub fn new(frame: &'a mut [[f32; 3]], width: usize, height: usize) -> Canvas<'a> {
+        assert_eq!(
+            frame.len(),
+            width * height,
+            "frame size must match the dimensions"
+        );
+        Canvas {
+            frame,
+            width,
+            height,
+        }
+    }
+
+    /// Converts the coordinates with the origin in the center of the canvas
+    /// to the index of the closest pixel in the frame.
+    fn index(&self, x: i64, y: i64) -> usize {
+        let x = (x + self.width as i64 / 2).clamp(0, self.width as i64 - 1) as usize;
+        let y = (self.height as i64 / 2 - y).clamp(0, self.height as i64 - 1) as usize;
+        y * self.width + x
+    }
+
+    /// Colors the pixel (x, y) on the canvas with the given color.
+    pub fn put_pixel(&mut self, x: i64, y: i64, color: &(f64, f64, f64)) {
+        let pos = self.index(x, y);
+        self.frame[pos] = [color.0 as f32, color.1 as f32, color.2 as f32];
+    }
+
+    /// Colors the pixel (x, y) on the canvas with the given color, if the new depth is closer than the old one.
+    pub fn put_pixel_depth(
+        &mut self,
+        x: i64,
+        y: i64,
+        z_inv: f64,
+        color: &(f64, f64, f64),
+        depth_buffer: &mut [f64],
+    ) {
+        let pos = self.index(x, y);
+        if depth_buffer[pos] < z_inv {
+            self.put_pixel(x, y, color);
+            depth_buffer[pos] = z_inv;
+        }
+    }
+}
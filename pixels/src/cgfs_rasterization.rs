@@ -1,3 +1,5 @@
+use std::cmp::Ordering;
+
 pub const TRIANGLE_POINTS: &[(i64, i64)] = &[(-200, -100), (240, 120), (-50, -200)];
 pub const TRIANGLE: &[(usize, usize)] = &[(0, 1), (1, 2), (2, 0)];
 pub const CUBE_POINTS: &[(f64, f64, f64)] = &[
@@ -10,7 +12,9 @@ pub const CUBE_POINTS: &[(f64, f64, f64)] = &[
     (-1., 0.5, 6.),  // vCb
     (-1., -0.5, 6.), // vDb
 ];
-pub const CUBE: &[((usize, usize), (f64, f64, f64))] = &[
+/// An edge between two points with a color.
+type ColoredEdge = ((usize, usize), (f64, f64, f64));
+pub const CUBE: &[ColoredEdge] = &[
     ((0, 1), (0., 0., 1.)),
     ((1, 2), (0., 0., 1.)),
     ((2, 3), (0., 0., 1.)),
@@ -36,121 +40,187 @@ macro_rules! swap_if {
     };
 }
 
-/// Helper for an inline `if` statement, for cosmetic reasons.
-macro_rules! inline_if {
-    ($a: expr, $b: expr, $c: expr) => {
-        if $c {
-            $a
-        } else {
-            $b
-        }
-    };
-}
-
-/// Iterator over an interpolated range.
-/// Can provide rounded or truncated values.
-struct Interpolate<Return, const ROUNDED: bool> {
+/// Iterator over an interpolated range with rounded values.
+struct Interpolate {
     i: i64,
     i1: i64,
     d: f64,
     a: f64,
-    output: std::marker::PhantomData<Return>,
 }
 
-impl<const ROUNDED: bool> Iterator for Interpolate<f64, ROUNDED> {
-    type Item = (i64, f64);
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.i < self.i1 {
-            self.i += 1;
-            self.d += self.a;
-            if ROUNDED {
-                Some((self.i, self.d.round()))
-            } else {
-                Some((self.i, self.d))
-            }
-        } else {
-            None
-        }
-    }
-}
-
-impl<const ROUNDED: bool> Iterator for Interpolate<i64, ROUNDED> {
+impl Iterator for Interpolate {
     type Item = (i64, i64);
     fn next(&mut self) -> Option<Self::Item> {
         if self.i < self.i1 {
             self.i += 1;
             self.d += self.a;
-            if ROUNDED {
-                Some((self.i, self.d.round() as i64))
-            } else {
-                Some((self.i, self.d as i64))
-            }
+            Some((self.i, self.d.round() as i64))
         } else {
             None
         }
     }
 }
 
-impl<Return, const ROUNDED: bool> Interpolate<Return, ROUNDED> {
-    fn left_of(&self, other: &Self) -> bool {
-        self.a < other.a
-    }
-}
-
-/// Linear interpolation between (i0, d0) and (i1, d1) with integer steps for i.
-fn interpolate(i0: i64, d0: f64, i1: i64, d1: f64) -> Interpolate<f64, false> {
+/// Linear interpolation between (i0, d0) and (i1, d1) with integer steps for i and rounded values for d.
+fn interpolate_rounded(i0: i64, d0: i64, i1: i64, d1: i64) -> Interpolate {
     let a = (d1 - d0) as f64 / (i1 - i0) as f64;
-    Interpolate::<f64, false> {
+    Interpolate {
         i: i0 - 1,
         i1,
         d: d0 as f64 - a,
         a,
-        output: std::marker::PhantomData,
     }
 }
 
-/// Linear interpolation between (i0, d0) and (i1, d1) with integer steps for i and rounded values for d.
-fn interpolate_rounded(i0: i64, d0: i64, i1: i64, d1: i64) -> Interpolate<i64, true> {
-    let a = (d1 - d0) as f64 / (i1 - i0) as f64;
-    Interpolate::<i64, true> {
-        i: i0 - 1,
-        i1,
-        d: d0 as f64 - a,
-        a,
-        output: std::marker::PhantomData,
+/// Values attached to the vertices of a triangle and interpolated across it,
+/// like colors, normals or texture coordinates.
+pub trait Attribute: Copy {
+    /// Linear interpolation from `self` at `t = 0` to `other` at `t = 1`.
+    fn lerp(&self, other: &Self, t: f64) -> Self;
+}
+
+/// No attributes, only the depth is interpolated.
+impl Attribute for () {
+    fn lerp(&self, _: &Self, _: f64) -> Self {}
+}
+
+impl Attribute for f64 {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        self + t * (other - self)
     }
 }
 
-/// Linear interpolation between (i0, d0, z0) and (i1, d1, z1) with integer steps for i and rounded values for d.
-fn interpolate_rounded_with_depth(
-    i0: i64,
-    d0: i64,
-    z0: f64,
-    i1: i64,
-    d1: i64,
-    z1: f64,
-) -> (Interpolate<i64, true>, Interpolate<f64, false>) {
-    let d = {
-        let a = (d1 - d0) as f64 / (i1 - i0) as f64;
-        Interpolate::<i64, true> {
-            i: i0 - 1,
-            i1,
-            d: d0 as f64 - a,
-            a,
-            output: std::marker::PhantomData,
+impl Attribute for (f64, f64, f64) {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        (
+            self.0.lerp(&other.0, t),
+            self.1.lerp(&other.1, t),
+            self.2.lerp(&other.2, t),
+        )
+    }
+}
+
+/// Pairs of attributes are interpolated together, e.g. texture coordinates `(f64, f64)`.
+impl<A: Attribute, B: Attribute> Attribute for (A, B) {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        (self.0.lerp(&other.0, t), self.1.lerp(&other.1, t))
+    }
+}
+
+/// Number of fractional bits of the fixed-point coordinates of the triangles' vertices on the canvas.
+/// Vertices keep their position between the pixel centers, so that they move smoothly with the camera.
+pub const SUBPIXEL_BITS: u32 = 8;
+
+/// Convert a coordinate on the canvas to fixed point with `SUBPIXEL_BITS` fractional bits.
+pub fn to_subpixels(x: f64) -> i64 {
+    (x * (1 << SUBPIXEL_BITS) as f64).round() as i64
+}
+
+/// Twice the signed area of the triangle (a, b, c), positive if it winds counterclockwise.
+fn signed_area(a: (i64, i64), b: (i64, i64), c: (i64, i64)) -> i64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+/// The edge function of a triangle's edge, evaluated at the pixel centers: twice the signed area of the triangle
+/// formed by the edge and the pixel center, positive left of the edge, zero on it and negative right of it.
+/// The edge function is exact in fixed point, so two triangles sharing an edge agree on the side of every pixel.
+/// Edges that are neither top nor left are biased by one to leave out the pixel centers right on them:
+/// by this top-left rule, such a pixel is drawn by exactly one of the triangles.
+#[derive(Clone, Copy)]
+struct Edge {
+    /// Biased value at the pixel (0, 0).
+    origin: i64,
+    /// Change of the value from pixel to pixel along x.
+    step_x: i64,
+    /// Change of the value from pixel to pixel along y.
+    step_y: i64,
+    bias: i64,
+}
+
+impl Edge {
+    /// The edge from `a` to `b` of a counterclockwise triangle, whose inside is left of the edge.
+    fn new(a: &(i64, i64, f64), b: &(i64, i64, f64)) -> Edge {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        // y points up, so the inside is below a top edge going left, and right of a left edge going down
+        let top_left = (dy == 0 && dx < 0) || dy < 0;
+        let bias = if top_left { 0 } else { 1 };
+        Edge {
+            origin: dy * a.0 - dx * a.1 - bias,
+            step_x: -dy << SUBPIXEL_BITS,
+            step_y: dx << SUBPIXEL_BITS,
+            bias,
         }
-    };
-    let z = {
-        let a = (z1 - z0) / (i1 - i0) as f64;
-        Interpolate::<f64, false> {
-            i: i0 - 1,
-            i1,
-            d: z0,
-            a,
-            output: std::marker::PhantomData,
+    }
+
+    /// Biased value at the pixel (x, y), the pixel is inside of the edge if it is not negative.
+    fn at(&self, x: i64, y: i64) -> i64 {
+        self.origin + x * self.step_x + y * self.step_y
+    }
+}
+
+/// A triangle prepared for rasterization: wound counterclockwise, with the edges opposite to each vertex.
+struct Setup<'a, A> {
+    edges: [Edge; 3],
+    /// Inverse depths of the vertices.
+    z_inv: [f64; 3],
+    attributes: [&'a A; 3],
+    /// Twice the area of the triangle.
+    area: f64,
+}
+
+impl<'a, A: Attribute> Setup<'a, A> {
+    /// Prepare the triangle, or `None` if it is degenerate and has no inside.
+    fn new(
+        p0: &(i64, i64, f64),
+        p1: &(i64, i64, f64),
+        p2: &(i64, i64, f64),
+        attributes: &'a [A; 3],
+    ) -> Option<Setup<'a, A>> {
+        let area = signed_area((p0.0, p0.1), (p1.0, p1.1), (p2.0, p2.1));
+        if area == 0 {
+            return None;
         }
-    };
-    (d, z)
+        let (p1, p2, attributes) = if area > 0 {
+            (p1, p2, [&attributes[0], &attributes[1], &attributes[2]])
+        } else {
+            (p2, p1, [&attributes[0], &attributes[2], &attributes[1]])
+        };
+        Some(Setup {
+            edges: [Edge::new(p1, p2), Edge::new(p2, p0), Edge::new(p0, p1)],
+            z_inv: [1. / p0.2, 1. / p1.2, 1. / p2.2],
+            attributes,
+            area: area.abs() as f64,
+        })
+    }
+
+    /// The inverse depth and the attributes at a pixel inside the triangle, given the biased edge functions there.
+    /// The edge functions are the barycentric coordinates of the pixel scaled by the area,
+    /// weighted by the inverse depths they interpolate the attributes with perspective correction.
+    fn interpolate(&self, w: [i64; 3]) -> (f64, A) {
+        let k = [0, 1, 2].map(|i| (w[i] + self.edges[i].bias) as f64 * self.z_inv[i]);
+        let sum = k[0] + k[1] + k[2];
+        let a = weighted(self.attributes, (k[0] / sum, k[1] / sum, k[2] / sum));
+        (sum / self.area, a)
+    }
+}
+
+/// The pixels within the bounding box of the triangle with fixed-point vertices, by its lowest and highest corner.
+pub fn pixel_bounds(
+    p0: &(i64, i64, f64),
+    p1: &(i64, i64, f64),
+    p2: &(i64, i64, f64),
+) -> ((i64, i64), (i64, i64)) {
+    let ceil = |v: i64| -(-v >> SUBPIXEL_BITS);
+    (
+        (
+            ceil(p0.0.min(p1.0).min(p2.0)),
+            ceil(p0.1.min(p1.1).min(p2.1)),
+        ),
+        (
+            p0.0.max(p1.0).max(p2.0) >> SUBPIXEL_BITS,
+            p0.1.max(p1.1).max(p2.1) >> SUBPIXEL_BITS,
+        ),
+    )
 }
 
 /// Given two points on the canvas, draw a line between them.
@@ -189,72 +259,58 @@ pub fn draw_filled_triangle<PutPixel>(
 ) where
     PutPixel: FnMut(i64, i64, &(f64, f64, f64)),
 {
-    // Sort the points so that y0 <= y1 <= y2
-    let (p0, p1) = swap_if!(p0, p1, p1.1 < p0.1);
-    let (p0, p2) = swap_if!(p0, p2, p2.1 < p0.1);
-    let (p1, p2) = swap_if!(p1, p2, p2.1 < p1.1);
-
-    // Compute the x coordinates of the triangle edges
-    let x01 = interpolate_rounded(p0.1, p0.0, p1.1, p1.0);
-    let mut x12 = interpolate_rounded(p1.1, p1.0, p2.1, p2.0);
-    let x02 = interpolate_rounded(p0.1, p0.0, p2.1, p2.0);
-
-    // Concatenate the short sides
-    let left = x02.left_of(&x01); // hack since we can't randomly access iterator values
-    x12.next();
-    let x012 = x01.chain(x12);
-    let shim = interpolate_rounded(0, 0, 0, 0); // placeholder so x012 and x02 are of the same type
-    let x02 = x02.chain(shim);
-
-    // Determine which is left and which is right
-    let lines = if left { x02.zip(x012) } else { x012.zip(x02) };
-
-    // Draw the horizontal segments
-    for ((y, x_l), (_, x_r)) in lines {
-        for x in x_l..=x_r {
-            put_pixel(x, y, color)
-        }
-    }
+    let fixed = |p: &(i64, i64)| (p.0 << SUBPIXEL_BITS, p.1 << SUBPIXEL_BITS, 1.);
+    draw_filled_triangle_with_depth(
+        &fixed(p0),
+        &fixed(p1),
+        &fixed(p2),
+        &[(); 3],
+        |x, y, _, _| put_pixel(x, y, color),
+    )
 }
 
-/// Given three points on the canvas, draw a triangle defined by them where it is not obstructed.
-pub fn draw_filled_triangle_with_depth<PutPixel>(
+/// Given three points on the canvas in fixed point with their depths, draw a triangle defined by them where it is not obstructed.
+/// The triangle is drawn row by row, from the first to the last pixel of the row inside all three edges,
+/// pixels right on an edge follow the top-left rule.
+/// The attributes of the vertices are interpolated with perspective correction:
+/// the attributes divided by the depth are interpolated together with the inverse depth and divided by it.
+/// `put_pixel` receives every pixel of the triangle with its inverse depth and interpolated attributes.
+pub fn draw_filled_triangle_with_depth<A, PutPixel>(
     p0: &(i64, i64, f64),
     p1: &(i64, i64, f64),
     p2: &(i64, i64, f64),
-    color: &(f64, f64, f64),
+    attributes: &[A; 3],
     mut put_pixel: PutPixel,
 ) where
-    PutPixel: FnMut(i64, i64, f64, &(f64, f64, f64)),
+    A: Attribute,
+    PutPixel: FnMut(i64, i64, f64, &A),
 {
-    // Sort the points so that y0 <= y1 <= y2
-    let (p0, p1) = swap_if!(p0, p1, p1.1 < p0.1);
-    let (p0, p2) = swap_if!(p0, p2, p2.1 < p0.1);
-    let (p1, p2) = swap_if!(p1, p2, p2.1 < p1.1);
-
-    // Compute the x coordinates of the triangle edges
-    let x01 = interpolate_rounded_with_depth(p0.1, p0.0, 1. / p0.2, p1.1, p1.0, 1. / p1.2);
-    let mut x12 = interpolate_rounded_with_depth(p1.1, p1.0, 1. / p1.2, p2.1, p2.0, 1. / p2.2);
-    let x02 = interpolate_rounded_with_depth(p0.1, p0.0, 1. / p0.2, p2.1, p2.0, 1. / p2.2);
-
-    // Concatenate the short sides
-    let left = x02.0.left_of(&x01.0); // hack since we can't randomly access iterator values
-    (x12.0.next(), x12.1.next());
-    let x012 = (x01.0.chain(x12.0), x01.1.chain(x12.1));
-    let shim = interpolate_rounded_with_depth(0, 0, 0., 0, 0, 0.); // placeholder so x012 and x02 are of the same type
-    let x02 = (x02.0.chain(shim.0), x02.1.chain(shim.1));
-
-    // Determine which is left and which is right
-    let lines = {
-        let x02 = x02.0.zip(x02.1);
-        let x012 = x012.0.zip(x012.1);
-        if left { x02.zip(x012) } else { x012.zip(x02) }
+    let triangle = match Setup::new(p0, p1, p2, attributes) {
+        Some(triangle) => triangle,
+        None => return,
     };
+    let ((x_min, y_min), (x_max, y_max)) = pixel_bounds(p0, p1, p2);
+    for y in y_min..=y_max {
+        // Narrow the row down to the pixels inside every edge
+        let (mut x_l, mut x_r) = (x_min, x_max);
+        for edge in &triangle.edges {
+            let w = edge.at(0, y);
+            match edge.step_x.cmp(&0) {
+                Ordering::Greater => x_l = x_l.max(-w.div_euclid(edge.step_x)),
+                Ordering::Less => x_r = x_r.min(w.div_euclid(-edge.step_x)),
+                Ordering::Equal if w < 0 => x_r = x_min - 1,
+                Ordering::Equal => {}
+            }
+        }
 
-    // Draw the horizontal segments
-    for (((y, x_l), (_, z_l)), ((_, x_r), (_, z_r))) in lines {
-        for (x, z) in interpolate(x_l, z_l, x_r, z_r) {
-            put_pixel(x, y, z, color)
+        // Draw the horizontal segment
+        let mut w = triangle.edges.map(|edge| edge.at(x_l, y));
+        for x in x_l..=x_r {
+            let (z, a) = triangle.interpolate(w);
+            put_pixel(x, y, z, &a);
+            for (w, edge) in w.iter_mut().zip(&triangle.edges) {
+                *w += edge.step_x;
+            }
         }
     }
 }
@@ -270,46 +326,119 @@ pub fn draw_shaded_triangle<PutPixel>(
 ) where
     PutPixel: FnMut(i64, i64, &(f64, f64, f64)),
 {
-    // Sort the points so that y0 <= y1 <= y2
-    let h = inline_if!((h.1, h.0, h.2), (h.0, h.1, h.2), p1.1 < p0.1);
-    let (p0, p1) = swap_if!(p0, p1, p1.1 < p0.1);
-    let h = inline_if!((h.2, h.1, h.0), (h.0, h.1, h.2), p2.1 < p0.1);
-    let (p0, p2) = swap_if!(p0, p2, p2.1 < p0.1);
-    let h = inline_if!((h.0, h.2, h.1), (h.0, h.1, h.2), p2.1 < p1.1);
-    let (p1, p2) = swap_if!(p1, p2, p2.1 < p1.1);
-
-    // Compute the x coordinates and h values of the triangle edges
-    let x01 = interpolate_rounded(p0.1, p0.0, p1.1, p1.0);
-    let h01 = interpolate(p0.1, h.0, p1.1, h.1);
-    let mut x12 = interpolate_rounded(p1.1, p1.0, p2.1, p2.0);
-    let mut h12 = interpolate(p1.1, h.1, p2.1, h.2);
-    let x02 = interpolate_rounded(p0.1, p0.0, p2.1, p2.0);
-    let h02 = interpolate(p0.1, h.0, p2.1, h.2);
-
-    // Concatenate the short sides
-    let left = x02.left_of(&x01); // hack since we can't randomly access iterator values
-    x12.next();
-    let x012 = x01.chain(x12);
-    let shim = interpolate_rounded(0, 0, 0, 0); // placeholder so x012 and x02 are of the same type
-    let x02 = x02.chain(shim);
-
-    h12.next();
-    let h012 = h01.chain(h12);
-    let shim = interpolate(0, 0., 0, 0.); // placeholder so h012 and h02 are of the same type
-    let h02 = h02.chain(shim);
-
-    // Determine which is left and which is right
-    let lines = if left {
-        x02.zip(h02).zip(x012.zip(h012))
+    let fixed = |p: &(i64, i64)| (p.0 << SUBPIXEL_BITS, p.1 << SUBPIXEL_BITS, 1.);
+    draw_filled_triangle_with_depth(
+        &fixed(p0),
+        &fixed(p1),
+        &fixed(p2),
+        &[h.0, h.1, h.2],
+        |x, y, _, h| {
+            let shaded_color = (color.0 * h, color.1 * h, color.2 * h);
+            put_pixel(x, y, &shaded_color)
+        },
+    )
+}
+
+/// Calculate the barycentric coordinates of the pixel (x, y) in the triangle with fixed-point vertices,
+/// the weights of its vertices for interpolating values across it.
+pub fn barycentric(
+    p0: &(i64, i64, f64),
+    p1: &(i64, i64, f64),
+    p2: &(i64, i64, f64),
+    x: i64,
+    y: i64,
+) -> (f64, f64, f64) {
+    let p = (x << SUBPIXEL_BITS, y << SUBPIXEL_BITS);
+    let (a, b, c) = ((p0.0, p0.1), (p1.0, p1.1), (p2.0, p2.1));
+    // twice the signed areas of the sub-triangles opposite to each vertex
+    let total = signed_area(a, b, c) as f64;
+    if total == 0. {
+        // a degenerate triangle has no inside, weigh the vertices equally
+        return (1. / 3., 1. / 3., 1. / 3.);
+    }
+    let w0 = signed_area(p, b, c) as f64 / total;
+    let w1 = signed_area(a, p, c) as f64 / total;
+    (w0, w1, 1. - w0 - w1)
+}
+
+/// Interpolate the attributes of the triangle's vertices at any pixel (x, y) on the canvas,
+/// with the same perspective correction as `draw_filled_triangle_with_depth`.
+pub fn perspective_attributes<A: Attribute>(
+    p0: &(i64, i64, f64),
+    p1: &(i64, i64, f64),
+    p2: &(i64, i64, f64),
+    attributes: &[A; 3],
+    x: i64,
+    y: i64,
+) -> A {
+    // the barycentric coordinates weighted by the inverse depths, normalized
+    let w = barycentric(p0, p1, p2, x, y);
+    let (w0, w1, w2) = (w.0 / p0.2, w.1 / p1.2, w.2 / p2.2);
+    let sum = w0 + w1 + w2;
+    weighted(
+        [&attributes[0], &attributes[1], &attributes[2]],
+        (w0 / sum, w1 / sum, w2 / sum),
+    )
+}
+
+/// The weighted sum of three attributes, with weights summing to one, as two linear interpolations.
+fn weighted<A: Attribute>(attributes: [&A; 3], w: (f64, f64, f64)) -> A {
+    let t = if w.0 + w.1 == 0. {
+        0.
     } else {
-        x012.zip(h012).zip(x02.zip(h02))
+        w.1 / (w.0 + w.1)
     };
+    attributes[0]
+        .lerp(attributes[1], t)
+        .lerp(attributes[2], w.2)
+}
 
-    // Draw the horizontal segments
-    for (((y, x_l), (_, h_l)), ((_, x_r), (_, h_r))) in lines {
-        for (x, h) in interpolate(x_l, h_l, x_r, h_r) {
-            let shaded_color = (color.0 * h, color.1 * h, color.2 * h);
-            put_pixel(x, y, &shaded_color)
+/// Given three points on the canvas in fixed point with their depths, draw the triangle defined by them
+/// by testing every pixel of its bounding box instead of spans, only within the rectangle `bounds` given by its lowest and highest pixel.
+/// A pixel belongs to the triangle if it is on the inner side of all three edges, pixels right on an edge
+/// follow the top-left rule, so it draws the same pixels as `draw_filled_triangle_with_depth`.
+/// The depth and attributes are interpolated like there.
+pub fn draw_filled_triangle_half_space<A, PutPixel>(
+    p0: &(i64, i64, f64),
+    p1: &(i64, i64, f64),
+    p2: &(i64, i64, f64),
+    attributes: &[A; 3],
+    bounds: &((i64, i64), (i64, i64)),
+    mut put_pixel: PutPixel,
+) where
+    A: Attribute,
+    PutPixel: FnMut(i64, i64, f64, &A),
+{
+    let triangle = match Setup::new(p0, p1, p2, attributes) {
+        Some(triangle) => triangle,
+        None => return,
+    };
+
+    // Bounding box of the triangle within the bounds
+    let ((x_min, y_min), (x_max, y_max)) = pixel_bounds(p0, p1, p2);
+    let (x_min, y_min) = (x_min.max(bounds.0 .0), y_min.max(bounds.0 .1));
+    let (x_max, y_max) = (x_max.min(bounds.1 .0), y_max.min(bounds.1 .1));
+
+    // Walk the bounding box, stepping the edge functions
+    let mut row = triangle.edges.map(|edge| edge.at(x_min, y_min));
+    for y in y_min..=y_max {
+        let mut w = row;
+        let mut inside = false;
+        for x in x_min..=x_max {
+            if w.iter().all(|&w| w >= 0) {
+                inside = true;
+                let (z, a) = triangle.interpolate(w);
+                put_pixel(x, y, z, &a)
+            } else if inside {
+                // the triangle is convex, the rest of the row is outside
+                break;
+            }
+            for (w, edge) in w.iter_mut().zip(&triangle.edges) {
+                *w += edge.step_x;
+            }
+        }
+        for (w, edge) in row.iter_mut().zip(&triangle.edges) {
+            *w += edge.step_y;
         }
     }
 }
@@ -335,3 +464,74 @@ pub fn project_vertex(
         (p.1 - o.1) * v.2 / (p.2 - o.2),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Number of times each pixel is drawn by the triangles, with each rasterizer.
+    fn coverage(triangles: &[[(i64, i64); 3]]) -> [HashMap<(i64, i64), u32>; 2] {
+        let mut counts = [HashMap::new(), HashMap::new()];
+        for t in triangles {
+            let [p0, p1, p2] = t.map(|p| (p.0, p.1, 1.));
+            draw_filled_triangle_with_depth(&p0, &p1, &p2, &[(); 3], |x, y, _, _| {
+                *counts[0].entry((x, y)).or_insert(0) += 1;
+            });
+            let bounds = pixel_bounds(&p0, &p1, &p2);
+            draw_filled_triangle_half_space(&p0, &p1, &p2, &[(); 3], &bounds, |x, y, _, _| {
+                *counts[1].entry((x, y)).or_insert(0) += 1;
+            });
+        }
+        counts
+    }
+
+    /// Splits the convex quad into two triangles along a diagonal, the second one wound the other way round,
+    /// and checks that every pixel inside the quad is drawn exactly once and none outside of it.
+    fn assert_watertight(quad: [(f64, f64); 4]) {
+        let q = quad.map(|p| (to_subpixels(p.0), to_subpixels(p.1)));
+        let triangles = [[q[0], q[1], q[2]], [q[0], q[3], q[2]]];
+        let ccw = if signed_area(q[0], q[1], q[2]) > 0 {
+            1
+        } else {
+            -1
+        };
+        for counts in coverage(&triangles) {
+            for y in -50..=50 {
+                for x in -50..=50 {
+                    let center = (x << SUBPIXEL_BITS, y << SUBPIXEL_BITS);
+                    let sides =
+                        [0, 1, 2, 3].map(|i| ccw * signed_area(q[i], q[(i + 1) % 4], center));
+                    let count = counts.get(&(x, y)).copied().unwrap_or(0);
+                    if sides.iter().all(|&s| s > 0) {
+                        assert_eq!(count, 1, "pixel ({x}, {y}) inside of {quad:?}");
+                    } else if sides.iter().any(|&s| s < 0) {
+                        assert_eq!(count, 0, "pixel ({x}, {y}) outside of {quad:?}");
+                    } else {
+                        assert!(count <= 1, "pixel ({x}, {y}) on the border of {quad:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shared_edge_is_watertight() {
+        assert_watertight([(-20.3, -10.7), (25.6, -14.1), (18.2, 19.9), (-12.5, 22.4)]);
+        // the shared edge runs through pixel centers
+        assert_watertight([(-10., -10.), (10., -10.), (10., 10.), (-10., 10.)]);
+    }
+
+    #[test]
+    fn cube_face_is_watertight() {
+        // the front face of a cube turned by 30 degrees to the right and 20 degrees up, in front of the camera
+        let (sin_y, cos_y) = 30_f64.to_radians().sin_cos();
+        let (sin_p, cos_p) = 20_f64.to_radians().sin_cos();
+        let face = [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)].map(|(x, y): (f64, f64)| {
+            let (x, z) = (x * cos_y - sin_y, x * sin_y + cos_y);
+            let (y, z) = (y * cos_p - z * sin_p, y * sin_p + z * cos_p);
+            (60. * x / (z + 4.), 60. * y / (z + 4.))
+        });
+        assert_watertight(face);
+    }
+}
@@ -1,36 +1,505 @@
-const BACKGROUND_COLOR: (f64, f64, f64) = (0., 0., 0.); // black
+use crate::bvh::{Aabb, Bvh};
+use crate::cgfs_scene::Instance;
+use crate::mandel::halton_2d;
+use crate::texture::{self, Environment, Texture};
+use rand::Rng;
+use serde::Deserialize;
+use std::cell::OnceCell;
+use std::error::Error;
+use std::path::Path;
+
 const EPS: f64 = 1e-6;
+/// Maximum number of bounces of a path, Russian roulette usually ends it much sooner.
+const MAX_PATH_LENGTH: usize = 64;
+/// Number of bounces after which Russian roulette starts ending the paths.
+const ROULETTE_START: usize = 3;
+/// Share of the light that surfaces with a specular exponent reflect glossily when path tracing.
+const GLOSSY_SHARE: f64 = 0.2;
+
+/// Geometric primitives the rays can hit.
+#[derive(Deserialize)]
+pub enum Shape {
+    Sphere {
+        center: (f64, f64, f64),
+        radius: f64,
+    },
+    /// An infinite plane through `point`.
+    Plane {
+        point: (f64, f64, f64),
+        normal: (f64, f64, f64),
+    },
+    Triangle {
+        a: (f64, f64, f64),
+        b: (f64, f64, f64),
+        c: (f64, f64, f64),
+    },
+    /// An axis-aligned box spanning from `min` to `max`.
+    Box {
+        min: (f64, f64, f64),
+        max: (f64, f64, f64),
+    },
+    Disc {
+        center: (f64, f64, f64),
+        normal: (f64, f64, f64),
+        radius: f64,
+    },
+}
+
+/// A shape with its surface properties.
+#[derive(Deserialize)]
+pub struct Object {
+    pub shape: Shape,
+    pub color: (f64, f64, f64),
+    pub specular: Option<f64>,
+    pub reflective: f64,
+    /// Share of the color coming from the light passing through the object.
+    #[serde(default)]
+    pub transparency: f64,
+    /// Index of refraction of the object's material, e.g. 1.5 for glass or 1.33 for water.
+    #[serde(default = "default_refractive_index")]
+    pub refractive_index: f64,
+    /// Light emitted by the object itself.
+    #[serde(default)]
+    pub emission: (f64, f64, f64),
+    /// Pattern replacing the constant color.
+    #[serde(default)]
+    pub texture: Option<Texture>,
+    /// How the surface is laid out in texture coordinates, chosen by the shape if omitted.
+    #[serde(default)]
+    pub mapping: Option<Mapping>,
+}
+
+/// Ways of assigning texture coordinates to the points on a surface.
+#[derive(Deserialize)]
+pub enum Mapping {
+    /// Longitude and latitude around the center of the object, both from 0 to 1.
+    Spherical,
+    /// Coordinates on the plane perpendicular to `normal`, in the scene's units.
+    Planar { normal: (f64, f64, f64) },
+}
+
+fn default_refractive_index() -> f64 {
+    1.
+}
+
+/// Light sources, white unless a color is given.
+#[derive(Deserialize)]
+pub enum Light {
+    Ambient {
+        intensity: f64,
+        #[serde(default = "white")]
+        color: (f64, f64, f64),
+    },
+    Point {
+        intensity: f64,
+        #[serde(default = "white")]
+        color: (f64, f64, f64),
+        position: (f64, f64, f64),
+        #[serde(default)]
+        attenuation: Attenuation,
+    },
+    Directional {
+        intensity: f64,
+        #[serde(default = "white")]
+        color: (f64, f64, f64),
+        direction: (f64, f64, f64),
+    },
+    /// A point light shining in a cone around `direction`.
+    Spot {
+        intensity: f64,
+        #[serde(default = "white")]
+        color: (f64, f64, f64),
+        position: (f64, f64, f64),
+        direction: (f64, f64, f64),
+        /// Angle between the direction and the edge of the cone in degrees.
+        angle: f64,
+        /// Share of the cone over which the light fades out towards its edge, 0 for a sharp edge.
+        #[serde(default)]
+        falloff: f64,
+        #[serde(default)]
+        attenuation: Attenuation,
+    },
+    /// An area light in the shape of the parallelogram spanned by `u` and `v` from `corner`.
+    Rectangle {
+        intensity: f64,
+        #[serde(default = "white")]
+        color: (f64, f64, f64),
+        corner: (f64, f64, f64),
+        u: (f64, f64, f64),
+        v: (f64, f64, f64),
+        #[serde(default)]
+        attenuation: Attenuation,
+    },
+    /// A spherical area light.
+    Sphere {
+        intensity: f64,
+        #[serde(default = "white")]
+        color: (f64, f64, f64),
+        center: (f64, f64, f64),
+        radius: f64,
+        #[serde(default)]
+        attenuation: Attenuation,
+    },
+    /// Ambient light of the scene's environment, coming from the directions the objects do not block.
+    Environment { intensity: f64 },
+}
+
+/// How the intensity of a light decreases with the distance from it.
+#[derive(Deserialize, Default)]
+pub enum Attenuation {
+    /// The same intensity at any distance.
+    #[default]
+    None,
+    /// Intensity divided by `constant + linear * distance + quadratic * distance^2`.
+    Polynomial {
+        constant: f64,
+        linear: f64,
+        quadratic: f64,
+    },
+    /// Physically based falloff with the square of the distance, the intensity is given at distance 1.
+    InverseSquare,
+}
+
+impl Attenuation {
+    /// Calculate the share of the light's intensity that reaches the given distance.
+    fn factor(&self, distance: f64) -> f64 {
+        match self {
+            Attenuation::None => 1.,
+            Attenuation::Polynomial {
+                constant,
+                linear,
+                quadratic,
+            } => 1. / (constant + linear * distance + quadratic * distance * distance),
+            Attenuation::InverseSquare => 1. / (distance * distance),
+        }
+    }
+
+    /// Checks that the attenuation cannot divide by zero or turn negative.
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Attenuation::Polynomial {
+                constant,
+                linear,
+                quadratic,
+            } => {
+                let coefficients = [constant, linear, quadratic];
+                if coefficients.iter().any(|&&k| k < 0.) {
+                    Err("polynomial attenuation coefficients must not be negative".to_string())
+                } else if coefficients.iter().all(|&&k| k == 0.) {
+                    Err("polynomial attenuation coefficients must not all be zero".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+            Attenuation::None | Attenuation::InverseSquare => Ok(()),
+        }
+    }
+}
+
+fn white() -> (f64, f64, f64) {
+    (1., 1., 1.)
+}
+
+fn default_shadow_samples() -> usize {
+    16
+}
+
+/// The camera's position, orientation and field of view.
+/// With both angles at 0 the camera looks along +z.
+#[derive(Deserialize)]
+pub struct Camera {
+    pub origin: (f64, f64, f64),
+    /// Rotation to the right around the vertical axis in degrees.
+    #[serde(default)]
+    pub yaw: f64,
+    /// Rotation upwards in degrees.
+    #[serde(default)]
+    pub pitch: f64,
+    /// Vertical field of view in degrees.
+    #[serde(default = "default_fov")]
+    pub fov: f64,
+    /// Radius of the lens, 0 for a pinhole camera with everything in focus.
+    #[serde(default)]
+    pub aperture: f64,
+    /// Distance from the camera to the plane in focus.
+    #[serde(default = "default_focal_distance")]
+    pub focal_distance: f64,
+    /// Point to look at, sets the angles when the scene is loaded.
+    #[serde(default)]
+    look_at: Option<(f64, f64, f64)>,
+    /// Deprecated viewport data from the book: (width, height, distance from camera).
+    /// Only sets the field of view, the aspect ratio follows the canvas.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    viewport: Option<(f64, f64, f64)>,
+}
+
+/// Reads an optional field written without `Some`, as in scenes from before it became optional.
+fn deserialize_some<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Field of view of the viewport from the book, of size 1 at distance 1.
+fn default_fov() -> f64 {
+    2. * 0.5_f64.atan().to_degrees()
+}
 
-type Sphere = (
-    (f64, f64, f64), // center
-    f64, // radius
-    (f64, f64, f64), // color
-    Option<f64>, // specular
-    f64 // reflective
-);
-const SPHERES: [Sphere; 4] = [
-    ((0., -1., 3.), 1., (1., 0., 0.), Some(500.), 0.2),
-    ((2., 0., 4.), 1., (0., 0., 1.), Some(500.), 0.3),
-    ((-2., 0., 4.), 1., (0., 1., 0.), Some(10.), 0.4),
-    ((0., -5001., 0.), 5000., (1., 1., 0.), Some(1000.), 0.5),
-];
-
-enum Light {
-    Ambient(f64),
-    Point(f64, (f64, f64, f64)),
-    Directional(f64, (f64, f64, f64)),
-}
-const LIGHTS: [Light; 3] = [
-    Light::Ambient(0.2),
-    Light::Point(0.6, (2., 1., 0.)),
-    Light::Directional(0.2, (1., 4., 4.)),
-];
+fn default_focal_distance() -> f64 {
+    1.
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera {
+            origin: (0., 0., 0.),
+            yaw: 0.,
+            pitch: 0.,
+            fov: default_fov(),
+            aperture: 0.,
+            focal_distance: default_focal_distance(),
+            look_at: None,
+            viewport: None,
+        }
+    }
+}
+
+/// Everything the raytracer needs to know about the world.
+/// Scenes are described in RON files, see `scenes/cgfs.ron` for an example.
+#[derive(Deserialize)]
+pub struct Scene {
+    #[serde(default)]
+    pub camera: Camera,
+    #[serde(default)]
+    pub background: (f64, f64, f64),
+    /// Sky or image surrounding the scene, replacing the background.
+    #[serde(default)]
+    pub environment: Option<Environment>,
+    /// Private, so that the objects cannot change without the BVH being rebuilt, see `objects`.
+    objects: Vec<Object>,
+    pub lights: Vec<Light>,
+    /// Number of shadow rays cast towards every area light, more of them give smoother penumbrae.
+    #[serde(default = "default_shadow_samples")]
+    pub shadow_samples: usize,
+    /// Acceleration structure over the objects, built along with them.
+    /// Without it, every object is tested against every ray.
+    #[serde(skip)]
+    pub bvh: Option<Bvh>,
+}
+
+impl Scene {
+    /// Parses a scene description and loads the images it refers to,
+    /// `dir` is the directory their paths are relative to and `srgb` decodes them to linear light.
+    pub fn parse(description: &str, dir: &Path, srgb: bool) -> Result<Scene, Box<dyn Error>> {
+        let mut scene: Scene = ron::from_str(description)?;
+        scene.validate()?;
+        for texture in scene.objects.iter_mut().filter_map(|o| o.texture.as_mut()) {
+            texture.load(dir, srgb)?;
+        }
+        if let Some(environment) = &mut scene.environment {
+            environment.load(dir, srgb)?;
+        }
+        if let Some((_, height, distance)) = scene.camera.viewport.take() {
+            scene.camera.fov = 2. * (height / 2.).atan2(distance).to_degrees();
+        }
+        if let Some(target) = scene.camera.look_at.take() {
+            scene.camera.look_at(&target);
+        }
+        scene.build_bvh();
+        Ok(scene)
+    }
+
+    /// Checks the values of a parsed scene that are out of range.
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for light in &self.lights {
+            match light {
+                Light::Point { attenuation, .. }
+                | Light::Spot { attenuation, .. }
+                | Light::Rectangle { attenuation, .. }
+                | Light::Sphere { attenuation, .. } => attenuation.validate()?,
+                Light::Ambient { .. } | Light::Directional { .. } | Light::Environment { .. } => {}
+            }
+        }
+        for texture in self.objects.iter().filter_map(|o| o.texture.as_ref()) {
+            texture.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Loads a scene description from a file, along with the textures it refers to.
+    pub fn load(path: &Path, srgb: bool) -> Result<Scene, Box<dyn Error>> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Scene::parse(&std::fs::read_to_string(path)?, dir, srgb)
+    }
+
+    /// Builds a scene out of the triangles of the rasterizer's model instances.
+    /// The scene is lit only by full ambient light, so it looks the same as when rasterized.
+    pub fn from_instances(instances: &[Instance]) -> Scene {
+        let objects = instances
+            .iter()
+            .flat_map(|instance| {
+                // transform the vertices into world space
+                let m = instance.transform();
+                let vertices = instance
+                    .model
+                    .vertices
+                    .iter()
+                    .map(|v| {
+                        let v = m * v;
+                        (v[0] / v[3], v[1] / v[3], v[2] / v[3])
+                    })
+                    .collect::<Vec<_>>();
+                instance
+                    .model
+                    .triangles
+                    .iter()
+                    .zip(instance.model.triangle_colors)
+                    .map(move |(&(a, b, c), &color)| Object {
+                        shape: Shape::Triangle {
+                            a: vertices[a],
+                            b: vertices[b],
+                            c: vertices[c],
+                        },
+                        color,
+                        specular: None,
+                        reflective: 0.,
+                        transparency: 0.,
+                        refractive_index: 1.,
+                        emission: (0., 0., 0.),
+                        texture: None,
+                        mapping: None,
+                    })
+            })
+            .collect();
+        let mut scene = Scene {
+            camera: Camera::default(),
+            background: (1., 1., 1.),
+            environment: None,
+            objects,
+            lights: vec![Light::Ambient {
+                intensity: 1.,
+                color: white(),
+            }],
+            shadow_samples: default_shadow_samples(),
+            bvh: None,
+        };
+        scene.build_bvh();
+        scene
+    }
+
+    /// The objects of the scene, the BVH is built over them when the scene is created.
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    /// Builds the bounding volume hierarchy over the scene's objects.
+    pub fn build_bvh(&mut self) {
+        let bounds = self
+            .objects
+            .iter()
+            .map(|object| object.shape.bounds())
+            .collect::<Vec<_>>();
+        self.bvh = Some(Bvh::new(&bounds));
+    }
+
+    /// Calculate the color seen by a ray going in the direction `d` that misses every object.
+    pub fn environment_color(&self, d: &(f64, f64, f64)) -> (f64, f64, f64) {
+        match &self.environment {
+            Some(environment) => environment.color(d),
+            None => self.background,
+        }
+    }
+}
+
+impl Default for Scene {
+    /// The scene from the book.
+    fn default() -> Scene {
+        Scene::parse(include_str!("../scenes/cgfs.ron"), Path::new(""), false).unwrap()
+    }
+}
 
 /// Projects a 3D point onto the viewport.
-pub fn canvas_to_viewport(v: &(f64, f64, f64), x: f64, y: f64, w: f64, h: f64) -> (f64, f64, f64) {
+fn canvas_to_viewport(v: &(f64, f64, f64), x: f64, y: f64, w: f64, h: f64) -> (f64, f64, f64) {
     (x * v.0 / w, y * v.1 / h, v.2)
 }
 
+impl Camera {
+    /// Rotate a vector from the camera's frame into the world, up by the pitch and then right by the yaw.
+    fn rotate(&self, v: &(f64, f64, f64)) -> (f64, f64, f64) {
+        let (sin_p, cos_p) = self.pitch.to_radians().sin_cos();
+        let (sin_y, cos_y) = self.yaw.to_radians().sin_cos();
+        let v = (v.0, v.1 * cos_p + v.2 * sin_p, v.2 * cos_p - v.1 * sin_p);
+        (v.0 * cos_y + v.2 * sin_y, v.1, v.2 * cos_y - v.0 * sin_y)
+    }
+
+    /// Calculate the origin and the direction of the ray through the point (x, y) on a canvas of the given size.
+    /// The viewport is at distance 1 and has the same aspect ratio as the canvas.
+    /// The ray passes through the point of the lens given by `lens` in the unit square, (0, 0) is its center.
+    pub fn ray(
+        &self,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        lens: (f64, f64),
+    ) -> ((f64, f64, f64), (f64, f64, f64)) {
+        let viewport_height = 2. * (self.fov.to_radians() / 2.).tan();
+        let v = (viewport_height * width / height, viewport_height, 1.);
+        let d = canvas_to_viewport(&v, x, y, width, height);
+        if self.aperture <= 0. {
+            return (self.origin, self.rotate(&d));
+        }
+
+        // rays through any point of the lens meet again on the plane in focus
+        let (r, phi) = (
+            self.aperture * lens.0.sqrt(),
+            2. * std::f64::consts::PI * lens.1,
+        );
+        let offset = (r * phi.cos(), r * phi.sin(), 0.);
+        let f = self.focal_distance;
+        // the direction is scaled to still reach the viewport at t = 1
+        let d = (d.0 - offset.0 / f, d.1 - offset.1 / f, d.2);
+        let offset = self.rotate(&offset);
+        (
+            (
+                self.origin.0 + offset.0,
+                self.origin.1 + offset.1,
+                self.origin.2 + offset.2,
+            ),
+            self.rotate(&d),
+        )
+    }
+
+    /// Turns the camera towards the target.
+    pub fn look_at(&mut self, target: &(f64, f64, f64)) {
+        let d = (
+            target.0 - self.origin.0,
+            target.1 - self.origin.1,
+            target.2 - self.origin.2,
+        );
+        self.yaw = d.0.atan2(d.2).to_degrees();
+        self.pitch = d.1.atan2((d.0 * d.0 + d.2 * d.2).sqrt()).to_degrees();
+    }
+
+    /// Turns the camera by the given angles in degrees, without tilting it over the vertical.
+    pub fn turn(&mut self, yaw: f64, pitch: f64) {
+        self.yaw = (self.yaw + yaw) % 360.;
+        self.pitch = (self.pitch + pitch).clamp(-90., 90.);
+    }
+
+    /// Moves the camera forward, to the right and up, the horizontal movement follows only the yaw.
+    pub fn fly(&mut self, forward: f64, right: f64, up: f64) {
+        let (sin_y, cos_y) = self.yaw.to_radians().sin_cos();
+        self.origin = (
+            self.origin.0 + forward * sin_y + right * cos_y,
+            self.origin.1 + up,
+            self.origin.2 + forward * cos_y - right * sin_y,
+        );
+    }
+}
+
 /// Helper for dot product calculation.
 macro_rules! dot3 {
     ($a: ident, $b: ident) => {
@@ -53,6 +522,26 @@ macro_rules! norm3 {
     }};
 }
 
+/// Helper for vector subtraction.
+macro_rules! sub3 {
+    ($a: expr, $b: expr) => {{
+        let (a, b) = ($a, $b);
+        (a.0 - b.0, a.1 - b.1, a.2 - b.2)
+    }};
+}
+
+/// Helper for cross product calculation.
+macro_rules! cross3 {
+    ($a: expr, $b: expr) => {{
+        let (a, b) = ($a, $b);
+        (
+            a.1 * b.2 - a.2 * b.1,
+            a.2 * b.0 - a.0 * b.2,
+            a.0 * b.1 - a.1 * b.0,
+        )
+    }};
+}
+
 /// Helper for linear interpolation of vectors.
 macro_rules! larp3 {
     ($a: ident, $b: ident, $r: expr) => {{
@@ -67,9 +556,13 @@ macro_rules! larp3 {
 }
 
 /// Calculate the two intersections of a ray with a sphere.
-fn intersect_ray_sphere(o: &(f64, f64, f64), d: &(f64, f64, f64), sphere: &Sphere) -> (f64, f64) {
-    let r = sphere.1;
-    let c0 = (o.0 - sphere.0 .0, o.1 - sphere.0 .1, o.2 - sphere.0 .2);
+fn intersect_ray_sphere(
+    o: &(f64, f64, f64),
+    d: &(f64, f64, f64),
+    center: &(f64, f64, f64),
+    r: f64,
+) -> (f64, f64) {
+    let c0 = sub3!(o, center);
 
     let a = dot3!(d, d);
     let b = 2. * dot3!(c0, d);
@@ -86,6 +579,220 @@ fn intersect_ray_sphere(o: &(f64, f64, f64), d: &(f64, f64, f64), sphere: &Spher
     }
 }
 
+/// Calculate the intersection of a ray with a plane.
+fn intersect_ray_plane(
+    o: &(f64, f64, f64),
+    d: &(f64, f64, f64),
+    point: &(f64, f64, f64),
+    n: &(f64, f64, f64),
+) -> f64 {
+    let n_dot_d = dot3!(n, d);
+    if n_dot_d.abs() < f64::EPSILON {
+        // the ray is parallel to the plane
+        f64::INFINITY
+    } else {
+        let op = sub3!(point, o);
+        dot3!(op, n) / n_dot_d
+    }
+}
+
+/// Calculate the intersection of a ray with a triangle using the Möller–Trumbore algorithm.
+fn intersect_ray_triangle(
+    o: &(f64, f64, f64),
+    d: &(f64, f64, f64),
+    a: &(f64, f64, f64),
+    b: &(f64, f64, f64),
+    c: &(f64, f64, f64),
+) -> f64 {
+    let e1 = sub3!(b, a);
+    let e2 = sub3!(c, a);
+    let pv = cross3!(d, e2);
+    let det = dot3!(e1, pv);
+    if det.abs() < f64::EPSILON {
+        // the ray is parallel to the triangle
+        return f64::INFINITY;
+    }
+    let tv = sub3!(o, a);
+    let u = dot3!(tv, pv) / det;
+    if !(0. ..=1.).contains(&u) {
+        return f64::INFINITY;
+    }
+    let qv = cross3!(tv, e1);
+    let v = dot3!(d, qv) / det;
+    if v < 0. || u + v > 1. {
+        return f64::INFINITY;
+    }
+    dot3!(e2, qv) / det
+}
+
+/// Calculate the two intersections of a ray with an axis-aligned box using the slab method.
+fn intersect_ray_box(
+    o: &(f64, f64, f64),
+    d: &(f64, f64, f64),
+    min: &(f64, f64, f64),
+    max: &(f64, f64, f64),
+) -> (f64, f64) {
+    let mut t_near = f64::NEG_INFINITY;
+    let mut t_far = f64::INFINITY;
+    for (o, d, min, max) in [
+        (o.0, d.0, min.0, max.0),
+        (o.1, d.1, min.1, max.1),
+        (o.2, d.2, min.2, max.2),
+    ] {
+        let (t0, t1) = ((min - o) / d, (max - o) / d);
+        t_near = t_near.max(t0.min(t1));
+        t_far = t_far.min(t0.max(t1));
+    }
+    if t_near > t_far {
+        (f64::INFINITY, f64::INFINITY)
+    } else {
+        (t_near, t_far)
+    }
+}
+
+impl Shape {
+    /// Calculate the intersections of a ray with the shape.
+    /// Shapes with a single intersection return it twice.
+    fn intersect(&self, o: &(f64, f64, f64), d: &(f64, f64, f64)) -> (f64, f64) {
+        match self {
+            Shape::Sphere { center, radius } => intersect_ray_sphere(o, d, center, *radius),
+            Shape::Plane { point, normal } => {
+                let t = intersect_ray_plane(o, d, point, normal);
+                (t, t)
+            }
+            Shape::Triangle { a, b, c } => {
+                let t = intersect_ray_triangle(o, d, a, b, c);
+                (t, t)
+            }
+            Shape::Box { min, max } => intersect_ray_box(o, d, min, max),
+            Shape::Disc {
+                center,
+                normal,
+                radius,
+            } => {
+                let t = intersect_ray_plane(o, d, center, normal);
+                let p = (o.0 + t * d.0, o.1 + t * d.1, o.2 + t * d.2);
+                let cp = sub3!(p, center);
+                if dot3!(cp, cp) <= radius * radius {
+                    (t, t)
+                } else {
+                    (f64::INFINITY, f64::INFINITY)
+                }
+            }
+        }
+    }
+
+    /// Calculate the bounding box of the shape, `None` if the shape is unbounded.
+    fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Shape::Sphere { center, radius } => Some(Aabb {
+                min: (center.0 - radius, center.1 - radius, center.2 - radius),
+                max: (center.0 + radius, center.1 + radius, center.2 + radius),
+            }),
+            Shape::Plane { .. } => None,
+            // flat shapes are padded, so that their boxes are not degenerate
+            Shape::Triangle { a, b, c } => Some(Aabb::around(&[*a, *b, *c]).padded(EPS)),
+            Shape::Box { min, max } => Some(Aabb {
+                min: *min,
+                max: *max,
+            }),
+            Shape::Disc {
+                center,
+                normal,
+                radius,
+            } => {
+                // the disc's extent along an axis shrinks as the normal aligns with it
+                let n = norm3!(normal);
+                let e = (
+                    radius * (1. - n.0 * n.0).max(0.).sqrt(),
+                    radius * (1. - n.1 * n.1).max(0.).sqrt(),
+                    radius * (1. - n.2 * n.2).max(0.).sqrt(),
+                );
+                Some(
+                    Aabb {
+                        min: sub3!(center, e),
+                        max: (center.0 + e.0, center.1 + e.1, center.2 + e.2),
+                    }
+                    .padded(EPS),
+                )
+            }
+        }
+    }
+
+    /// Calculate the unit normal of the shape at a point on its surface.
+    fn normal(&self, p: &(f64, f64, f64)) -> (f64, f64, f64) {
+        match self {
+            Shape::Sphere { center, .. } => {
+                let n = sub3!(p, center);
+                norm3!(n)
+            }
+            Shape::Plane { normal, .. } | Shape::Disc { normal, .. } => norm3!(normal),
+            Shape::Triangle { a, b, c } => {
+                let n = cross3!(sub3!(b, a), sub3!(c, a));
+                norm3!(n)
+            }
+            Shape::Box { min, max } => {
+                // the normal points along the axis on which the point is the furthest from the center
+                let q = (
+                    (2. * p.0 - min.0 - max.0) / (max.0 - min.0),
+                    (2. * p.1 - min.1 - max.1) / (max.1 - min.1),
+                    (2. * p.2 - min.2 - max.2) / (max.2 - min.2),
+                );
+                if q.0.abs() >= q.1.abs() && q.0.abs() >= q.2.abs() {
+                    (q.0.signum(), 0., 0.)
+                } else if q.1.abs() >= q.2.abs() {
+                    (0., q.1.signum(), 0.)
+                } else {
+                    (0., 0., q.2.signum())
+                }
+            }
+        }
+    }
+
+    /// Calculate the texture coordinates of a point on the surface of the shape.
+    /// Spheres are mapped spherically and the flat shapes onto their planes, unless a mapping is given.
+    fn uv(&self, p: &(f64, f64, f64), mapping: Option<&Mapping>) -> (f64, f64) {
+        let spherical = |center: &(f64, f64, f64)| {
+            // the middle of the texture faces -z, towards the default camera
+            let d = sub3!(p, center);
+            let d = norm3!(d);
+            (
+                0.5 + d.0.atan2(-d.2) / (2. * std::f64::consts::PI),
+                0.5 + d.1.clamp(-1., 1.).asin() / std::f64::consts::PI,
+            )
+        };
+        let planar = |normal: &(f64, f64, f64)| {
+            let n = norm3!(normal);
+            let (e1, e2) = orthonormal_basis(&n);
+            (dot3!(p, e1), dot3!(p, e2))
+        };
+        match (mapping, self) {
+            (Some(Mapping::Planar { normal }), _) => planar(normal),
+            (Some(Mapping::Spherical), _) | (None, Shape::Sphere { .. }) => {
+                let center = self.bounds().map_or((0., 0., 0.), |b| {
+                    (
+                        (b.min.0 + b.max.0) / 2.,
+                        (b.min.1 + b.max.1) / 2.,
+                        (b.min.2 + b.max.2) / 2.,
+                    )
+                });
+                spherical(&center)
+            }
+            (None, _) => planar(&self.normal(p)),
+        }
+    }
+}
+
+impl Object {
+    /// Calculate the color of the object at a point on its surface.
+    fn color_at(&self, p: &(f64, f64, f64)) -> (f64, f64, f64) {
+        match &self.texture {
+            Some(texture) => texture.color(&self.color, p, self.shape.uv(p, self.mapping.as_ref())),
+            None => self.color,
+        }
+    }
+}
+
 /// Reflect a ray off a surface.
 fn reflect_ray(r: &(f64, f64, f64), n: &(f64, f64, f64)) -> (f64, f64, f64) {
     let n_dot_r = dot3!(n, r);
@@ -96,44 +803,227 @@ fn reflect_ray(r: &(f64, f64, f64), n: &(f64, f64, f64)) -> (f64, f64, f64) {
     )
 }
 
-/// Computes a lighting at a given point in space.
+/// Refract a ray on a surface using Snell's law.
+/// `eta` is the ratio of the refractive indices of the two materials, `n` faces the incoming ray.
+/// Returns `None` on total internal reflection.
+fn refract_ray(d: &(f64, f64, f64), n: &(f64, f64, f64), eta: f64) -> Option<(f64, f64, f64)> {
+    let d = norm3!(d);
+    let cos_i = -dot3!(n, d);
+    let sin2_t = eta * eta * (1. - cos_i * cos_i);
+    if sin2_t > 1. {
+        return None;
+    }
+    let k = eta * cos_i - (1. - sin2_t).sqrt();
+    Some((
+        eta * d.0 + k * n.0,
+        eta * d.1 + k * n.1,
+        eta * d.2 + k * n.2,
+    ))
+}
+
+/// Calculate the share of the light reflected off a surface between two materials
+/// using Schlick's approximation of the Fresnel equations.
+/// `cos` is the cosine of the angle in the less dense material.
+fn fresnel(n1: f64, n2: f64, cos: f64) -> f64 {
+    let r0 = ((n1 - n2) / (n1 + n2)).powi(2);
+    r0 + (1. - r0) * (1. - cos).powi(5)
+}
+
+/// Adds the light of the given color and intensity to the lighting.
+fn add_light(i: &mut (f64, f64, f64), color: &(f64, f64, f64), intensity: f64) {
+    i.0 += intensity * color.0;
+    i.1 += intensity * color.1;
+    i.2 += intensity * color.2;
+}
+
+/// Calculate two unit vectors perpendicular to the unit vector `w` and to each other.
+fn orthonormal_basis(w: &(f64, f64, f64)) -> ((f64, f64, f64), (f64, f64, f64)) {
+    let a = if w.0.abs() > 0.9 {
+        (0., 1., 0.)
+    } else {
+        (1., 0., 0.)
+    };
+    let e1 = cross3!(a, w);
+    let e1 = norm3!(e1);
+    (e1, cross3!(w, e1))
+}
+
+/// Calculate the share of a spot light's intensity that reaches along `l`.
+fn spot_factor(l: &(f64, f64, f64), direction: &(f64, f64, f64), angle: f64, falloff: f64) -> f64 {
+    let cos = -dot3!(l, direction) / (len3!(l) * len3!(direction));
+    let cos_outer = angle.to_radians().cos();
+    let cos_inner = (angle * (1. - falloff.clamp(0., 1.))).to_radians().cos();
+    if cos_inner - cos_outer <= 0. {
+        if cos >= cos_outer {
+            1.
+        } else {
+            0.
+        }
+    } else {
+        // smoothstep between the edge of the cone and its fully lit inner part
+        let t = ((cos - cos_outer) / (cos_inner - cos_outer)).clamp(0., 1.);
+        t * t * (3. - 2. * t)
+    }
+}
+
+/// The first points of the Halton sequence in the unit square, shifted by an offset and wrapped around
+/// (Cranley-Patterson rotation). The offset is taken from the bits of `seed`, see `point_seed`.
+fn rotated_halton(seed: u64, samples: usize) -> impl Iterator<Item = (f64, f64)> {
+    let unit = |bits: u64| bits as f64 / (1_u64 << 32) as f64;
+    let (da, db) = (unit(seed >> 32), unit(seed & 0xffff_ffff));
+    halton_2d::<2, 3>()
+        .take(samples)
+        .map(move |(a, b)| ((a + da).fract(), (b + db).fract()))
+}
+
+/// Hashes the shading point `p` into the seed of its sampling pattern. Neighbouring points get different
+/// patterns, so that they do not share the same sampling errors, which would show as banding instead of noise,
+/// while the image stays the same in every run.
+fn point_seed(p: &(f64, f64, f64)) -> u64 {
+    texture::hash(
+        p.0.to_bits() as i64,
+        p.1.to_bits() as i64,
+        p.2.to_bits() as i64,
+    )
+}
+
+/// Computes a lighting at a given point in space, separately for each color channel.
+/// Ambient lights can be left out, e.g. when the indirect light is traced instead.
 fn compute_lighting(
+    scene: &Scene,
     p: &(f64, f64, f64),
     n: &(f64, f64, f64),
     v: &(f64, f64, f64),
     s: Option<f64>,
-) -> f64 {
-    let mut i = 0.;
-    for light in LIGHTS.iter() {
-        if let Light::Ambient(intensity) = light {
-            i += intensity;
-        } else {
-            let (intensity, l, t_max) = match light {
-                Light::Point(intensity, position) => (
-                    intensity,
-                    (position.0 - p.0, position.1 - p.1, position.2 - p.2),
-                    1.,
-                ),
-                Light::Directional(intensity, direction) => (intensity, *direction, f64::INFINITY),
-                _ => panic!("Never"),
-            };
+    ambient: bool,
+) -> (f64, f64, f64) {
+    // Adds the light coming from the direction `l`, unless it is in shadow
+    let illuminate = |i: &mut (f64, f64, f64),
+                      color: &(f64, f64, f64),
+                      intensity: f64,
+                      l: (f64, f64, f64),
+                      t_max: f64| {
+        // Shadow check
+        if any_intersection(scene, p, &l, EPS, t_max) {
+            return;
+        }
 
-            // Shadow check
-            let (shadow_sphere, _) = closest_intersection(p, &l, EPS, t_max);
-            if let None = shadow_sphere {
-                // Diffuse
-                let n_dot_l = dot3!(n, l);
-                if n_dot_l > 0. {
-                    i += intensity * n_dot_l / (len3!(n) * len3!(l));
-                }
+        // Diffuse
+        let n_dot_l = dot3!(n, l);
+        if n_dot_l > 0. {
+            add_light(i, color, intensity * n_dot_l / (len3!(n) * len3!(l)));
+        }
+
+        // Specular
+        if let Some(s) = s {
+            let r = reflect_ray(&l, n);
+            let r_dot_v = dot3!(r, v);
+            if r_dot_v > 0. {
+                let powf = r_dot_v / (len3!(r) * len3!(v));
+                add_light(i, color, intensity * powf.powf(s));
+            }
+        }
+    };
 
-                // Specular
-                if let Some(s) = s {
-                    let r = reflect_ray(&l, n);
-                    let r_dot_v = dot3!(r, v);
-                    if r_dot_v > 0. {
-                        let powf = r_dot_v / (len3!(r) * len3!(v));
-                        i += intensity * powf.powf(s);
+    let mut i = (0., 0., 0.);
+    // area lights are sampled in points of a randomly rotated low-discrepancy sequence, each carrying an equal share
+    let samples = scene.shadow_samples.max(1);
+    for light in scene.lights.iter() {
+        match light {
+            Light::Ambient { intensity, color } => {
+                if ambient {
+                    add_light(&mut i, color, *intensity)
+                }
+            }
+            Light::Point {
+                intensity,
+                color,
+                position,
+                attenuation,
+            } => {
+                let l = sub3!(position, p);
+                let intensity = intensity * attenuation.factor(len3!(l));
+                illuminate(&mut i, color, intensity, l, 1.)
+            }
+            Light::Directional {
+                intensity,
+                color,
+                direction,
+            } => illuminate(&mut i, color, *intensity, *direction, f64::INFINITY),
+            Light::Spot {
+                intensity,
+                color,
+                position,
+                direction,
+                angle,
+                falloff,
+                attenuation,
+            } => {
+                let l = sub3!(position, p);
+                let factor =
+                    spot_factor(&l, direction, *angle, *falloff) * attenuation.factor(len3!(l));
+                if factor > 0. {
+                    illuminate(&mut i, color, intensity * factor, l, 1.);
+                }
+            }
+            Light::Rectangle {
+                intensity,
+                color,
+                corner,
+                u,
+                v,
+                attenuation,
+            } => {
+                for (a, b) in rotated_halton(point_seed(p), samples) {
+                    let l = (
+                        corner.0 + a * u.0 + b * v.0 - p.0,
+                        corner.1 + a * u.1 + b * v.1 - p.1,
+                        corner.2 + a * u.2 + b * v.2 - p.2,
+                    );
+                    let intensity = intensity * attenuation.factor(len3!(l)) / samples as f64;
+                    illuminate(&mut i, color, intensity, l, 1.);
+                }
+            }
+            Light::Sphere {
+                intensity,
+                color,
+                center,
+                radius,
+                attenuation,
+            } => {
+                // sample the disc of the sphere that faces the point
+                let w = sub3!(p, center);
+                let w = norm3!(w);
+                let (e1, e2) = orthonormal_basis(&w);
+                for (a, b) in rotated_halton(point_seed(p), samples) {
+                    let (r, phi) = (radius * a.sqrt(), 2. * std::f64::consts::PI * b);
+                    let (x, y) = (r * phi.cos(), r * phi.sin());
+                    let l = (
+                        center.0 + x * e1.0 + y * e2.0 - p.0,
+                        center.1 + x * e1.1 + y * e2.1 - p.1,
+                        center.2 + x * e1.2 + y * e2.2 - p.2,
+                    );
+                    let intensity = intensity * attenuation.factor(len3!(l)) / samples as f64;
+                    illuminate(&mut i, color, intensity, l, 1.);
+                }
+            }
+            Light::Environment { intensity } => {
+                if !ambient {
+                    continue;
+                }
+                // gather the unblocked environment over the cosine-weighted hemisphere around the normal
+                let (e1, e2) = orthonormal_basis(n);
+                for (a, b) in rotated_halton(point_seed(p), samples) {
+                    let (r, phi) = (a.sqrt(), 2. * std::f64::consts::PI * b);
+                    let (x, y, z) = (r * phi.cos(), r * phi.sin(), (1. - a).sqrt());
+                    let l = (
+                        x * e1.0 + y * e2.0 + z * n.0,
+                        x * e1.1 + y * e2.1 + z * n.1,
+                        x * e1.2 + y * e2.2 + z * n.2,
+                    );
+                    if !any_intersection(scene, p, &l, EPS, f64::INFINITY) {
+                        let color = scene.environment_color(&l);
+                        add_light(&mut i, &color, intensity / samples as f64);
                     }
                 }
             }
@@ -142,59 +1032,358 @@ fn compute_lighting(
     i
 }
 
-/// Determine which sphere intersects the ray first.
+/// Calculate the closest intersection of a ray with an object within [t_min, t_max], if any.
+fn intersect_object(
+    object: &Object,
+    o: &(f64, f64, f64),
+    d: &(f64, f64, f64),
+    t_min: f64,
+    t_max: f64,
+) -> Option<f64> {
+    let (t1, t2) = object.shape.intersect(o, d);
+    [t1, t2]
+        .into_iter()
+        // misses are reported as infinitely far away
+        .filter(|t| t.is_finite() && t_min <= *t && *t <= t_max)
+        .reduce(f64::min)
+}
+
+/// Determine which object intersects the ray first.
 fn closest_intersection<'a>(
+    scene: &'a Scene,
     o: &(f64, f64, f64),
     d: &(f64, f64, f64),
     t_min: f64,
     t_max: f64,
-) -> (Option<&'a Sphere>, f64) {
+) -> (Option<&'a Object>, f64) {
+    let hit = |i: usize, t_max: f64| intersect_object(&scene.objects[i], o, d, t_min, t_max);
+    if let Some(bvh) = &scene.bvh {
+        return match bvh.closest(o, d, t_min, t_max, hit) {
+            Some((i, t)) => (Some(&scene.objects[i]), t),
+            None => (None, f64::INFINITY),
+        };
+    }
+
     let mut closest_t = f64::INFINITY;
-    let mut closest_sphere: Option<&Sphere> = None;
-    for sphere in SPHERES.iter() {
-        let (t1, t2) = intersect_ray_sphere(o, d, sphere);
-        if t_min <= t1 && t1 <= t_max && t1 < closest_t {
-            closest_t = t1;
-            closest_sphere = Some(sphere)
-        }
-        if t_min <= t2 && t2 <= t_max && t2 < closest_t {
-            closest_t = t2;
-            closest_sphere = Some(sphere)
+    let mut closest_object: Option<&Object> = None;
+    for object in scene.objects.iter() {
+        if let Some(t) = intersect_object(object, o, d, t_min, t_max) {
+            if t < closest_t {
+                closest_t = t;
+                closest_object = Some(object)
+            }
         }
     }
-    (closest_sphere, closest_t)
+    (closest_object, closest_t)
+}
+
+/// Determine whether the ray intersects any object.
+fn any_intersection(
+    scene: &Scene,
+    o: &(f64, f64, f64),
+    d: &(f64, f64, f64),
+    t_min: f64,
+    t_max: f64,
+) -> bool {
+    let hit = |i: usize, t_max: f64| intersect_object(&scene.objects[i], o, d, t_min, t_max);
+    match &scene.bvh {
+        Some(bvh) => bvh.any(o, d, t_min, t_max, hit),
+        None => scene
+            .objects
+            .iter()
+            .any(|object| intersect_object(object, o, d, t_min, t_max).is_some()),
+    }
 }
 
 /// Trace a ray.
 pub fn trace_ray(
+    scene: &Scene,
     o: &(f64, f64, f64),
     d: &(f64, f64, f64),
     t_min: f64,
     t_max: f64,
     recursion_depth: u64,
 ) -> (f64, f64, f64) {
-    let (closest_sphere, closest_t) = closest_intersection(o, d, t_min, t_max);
-    if let Some(s) = closest_sphere {
+    let (closest_object, closest_t) = closest_intersection(scene, o, d, t_min, t_max);
+    if let Some(s) = closest_object {
         // Compute local color
         let p = (
             o.0 + closest_t * d.0,
             o.1 + closest_t * d.1,
             o.2 + closest_t * d.2,
         );
-        let n = (p.0 - s.0 .0, p.1 - s.0 .1, p.2 - s.0 .2);
-        let n = norm3!(n);
-        let i = compute_lighting(&p, &n, &(-d.0, -d.1, -d.2), s.3);
-        let local_color = (s.2 .0 * i, s.2 .1 * i, s.2 .2 * i);
-        if recursion_depth == 0 || s.4 <= 0. {
-            local_color
+        let n = s.shape.normal(&p);
+        // the ray leaves the object if it hits the surface from the inside
+        let exiting = dot3!(n, d) > 0.;
+        // flat shapes can be seen from both sides => make the normal face the ray
+        let n = if exiting { (-n.0, -n.1, -n.2) } else { n };
+        let i = compute_lighting(scene, &p, &n, &(-d.0, -d.1, -d.2), s.specular, true);
+        let c = s.color_at(&p);
+        let local_color = (
+            c.0 * i.0 + s.emission.0,
+            c.1 * i.1 + s.emission.1,
+            c.2 * i.2 + s.emission.2,
+        );
+        if recursion_depth == 0 || (s.reflective <= 0. && s.transparency <= 0.) {
+            return local_color;
+        }
+
+        // Compute the reflected color, only once and only if it is seen:
+        // an object that is not reflective still reflects by the Fresnel equations if it is transparent
+        let reflection = OnceCell::new();
+        let reflected_color = || {
+            *reflection.get_or_init(|| {
+                let r = (-d.0, -d.1, -d.2);
+                let r = reflect_ray(&r, &n);
+                trace_ray(scene, &p, &r, EPS, f64::INFINITY, recursion_depth - 1)
+            })
+        };
+        let color = if s.reflective > 0. {
+            let reflected_color = reflected_color();
+            larp3!(local_color, reflected_color, s.reflective)
         } else {
-            // Compute the reflected color
-            let r = (-d.0, -d.1, -d.2);
-            let r = reflect_ray(&r, &n);
-            let reflected_color = trace_ray(&p, &r, EPS, f64::INFINITY, recursion_depth - 1);
-            larp3!(local_color, reflected_color, s.4)
+            local_color
+        };
+        if s.transparency <= 0. {
+            return color;
         }
+
+        // Compute the refracted color, assuming the object is surrounded by air
+        let (n1, n2) = if exiting {
+            (s.refractive_index, 1.)
+        } else {
+            (1., s.refractive_index)
+        };
+        let see_through_color = match refract_ray(d, &n, n1 / n2) {
+            Some(t) => {
+                let refracted_color =
+                    trace_ray(scene, &p, &t, EPS, f64::INFINITY, recursion_depth - 1);
+                // the angle in the less dense material determines the reflectance
+                let cos = if n1 <= n2 {
+                    -dot3!(n, d) / len3!(d)
+                } else {
+                    -dot3!(n, t) / len3!(t)
+                };
+                let reflectance = fresnel(n1, n2, cos);
+                let reflected_color = reflected_color();
+                larp3!(refracted_color, reflected_color, reflectance)
+            }
+            // total internal reflection
+            None => reflected_color(),
+        };
+        larp3!(color, see_through_color, s.transparency)
     } else {
-        BACKGROUND_COLOR
+        scene.environment_color(d)
+    }
+}
+
+/// Sample a direction around the unit vector `w`, with the probability proportional to `cos^exponent`
+/// of the angle between them. The exponent 1 gives the cosine-weighted hemisphere of a diffuse surface.
+fn sample_lobe<R: Rng>(w: &(f64, f64, f64), exponent: f64, rng: &mut R) -> (f64, f64, f64) {
+    let (e1, e2) = orthonormal_basis(w);
+    let cos = rng.gen::<f64>().powf(1. / (exponent + 1.));
+    let sin = (1. - cos * cos).max(0.).sqrt();
+    let phi = 2. * std::f64::consts::PI * rng.gen::<f64>();
+    let (x, y) = (sin * phi.cos(), sin * phi.sin());
+    (
+        x * e1.0 + y * e2.0 + cos * w.0,
+        x * e1.1 + y * e2.1 + cos * w.1,
+        x * e1.2 + y * e2.2 + cos * w.2,
+    )
+}
+
+/// Trace a path of light bounces through the scene, a single sample of the Monte Carlo estimate
+/// of the light arriving along the ray.
+/// At every bounce the light is either reflected, refracted or scattered by the surface,
+/// chosen at random by the object's material. Scattering surfaces also gather the direct light
+/// from the scene's lights, while the ambient lights are replaced by the traced indirect light.
+pub fn trace_path<R: Rng>(
+    scene: &Scene,
+    o: &(f64, f64, f64),
+    d: &(f64, f64, f64),
+    rng: &mut R,
+) -> (f64, f64, f64) {
+    let mut color = (0., 0., 0.);
+    // share of the light along the path that reaches the camera
+    let mut throughput = (1., 1., 1.);
+    let (mut o, mut d) = (*o, *d);
+    // the camera's rays start at the viewport
+    let mut t_min = 1.;
+    for bounce in 0..MAX_PATH_LENGTH {
+        let (closest_object, closest_t) = closest_intersection(scene, &o, &d, t_min, f64::INFINITY);
+        let s = match closest_object {
+            Some(s) => s,
+            None => {
+                let background = scene.environment_color(&d);
+                color = (
+                    color.0 + throughput.0 * background.0,
+                    color.1 + throughput.1 * background.1,
+                    color.2 + throughput.2 * background.2,
+                );
+                break;
+            }
+        };
+        let p = (
+            o.0 + closest_t * d.0,
+            o.1 + closest_t * d.1,
+            o.2 + closest_t * d.2,
+        );
+        let n = s.shape.normal(&p);
+        let exiting = dot3!(n, d) > 0.;
+        let n = if exiting { (-n.0, -n.1, -n.2) } else { n };
+        color = (
+            color.0 + throughput.0 * s.emission.0,
+            color.1 + throughput.1 * s.emission.1,
+            color.2 + throughput.2 * s.emission.2,
+        );
+
+        let r = (-d.0, -d.1, -d.2);
+        let reflected = reflect_ray(&r, &n);
+        let choice = rng.gen::<f64>();
+        d = if choice < s.transparency {
+            // Refraction, or reflection with the probability given by the Fresnel equations
+            let (n1, n2) = if exiting {
+                (s.refractive_index, 1.)
+            } else {
+                (1., s.refractive_index)
+            };
+            match refract_ray(&d, &n, n1 / n2) {
+                Some(t) => {
+                    let cos = if n1 <= n2 {
+                        -dot3!(n, d) / len3!(d)
+                    } else {
+                        -dot3!(n, t) / len3!(t)
+                    };
+                    if rng.gen::<f64>() < fresnel(n1, n2, cos) {
+                        reflected
+                    } else {
+                        t
+                    }
+                }
+                None => reflected,
+            }
+        } else if choice < s.transparency + (1. - s.transparency) * s.reflective {
+            // Mirror reflection
+            reflected
+        } else {
+            // Scattering: direct light from the light sources
+            let i = compute_lighting(scene, &p, &n, &r, s.specular, false);
+            let c = s.color_at(&p);
+            color = (
+                color.0 + throughput.0 * c.0 * i.0,
+                color.1 + throughput.1 * c.1 * i.1,
+                color.2 + throughput.2 * c.2 * i.2,
+            );
+
+            // and the indirect light from a glossy or diffuse bounce
+            throughput = (throughput.0 * c.0, throughput.1 * c.1, throughput.2 * c.2);
+            let next = match s.specular {
+                Some(exponent) if rng.gen::<f64>() < GLOSSY_SHARE => {
+                    let reflected = norm3!(reflected);
+                    sample_lobe(&reflected, exponent, rng)
+                }
+                _ => sample_lobe(&n, 1., rng),
+            };
+            if dot3!(next, n) <= 0. {
+                // the glossy lobe reaches below the surface
+                break;
+            }
+            next
+        };
+        o = p;
+        t_min = EPS;
+
+        // Russian roulette, paths carrying little light are ended early
+        if bounce >= ROULETTE_START {
+            let survival = throughput.0.max(throughput.1).max(throughput.2).min(0.95);
+            if rng.gen::<f64>() >= survival {
+                break;
+            }
+            throughput = (
+                throughput.0 / survival,
+                throughput.1 / survival,
+                throughput.2 / survival,
+            );
+        }
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// A random point in the cube from -`size` to `size` on every axis.
+    fn random_point(rng: &mut StdRng, size: f64) -> (f64, f64, f64) {
+        (
+            rng.gen_range(-size..size),
+            rng.gen_range(-size..size),
+            rng.gen_range(-size..size),
+        )
+    }
+
+    #[test]
+    fn bvh_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let objects = (0..500)
+            .map(|_| {
+                let a = random_point(&mut rng, 10.);
+                let b = random_point(&mut rng, 1.);
+                let c = random_point(&mut rng, 1.);
+                Object {
+                    shape: Shape::Triangle {
+                        a,
+                        b: (a.0 + b.0, a.1 + b.1, a.2 + b.2),
+                        c: (a.0 + c.0, a.1 + c.1, a.2 + c.2),
+                    },
+                    color: white(),
+                    specular: None,
+                    reflective: 0.,
+                    transparency: 0.,
+                    refractive_index: 1.,
+                    emission: (0., 0., 0.),
+                    texture: None,
+                    mapping: None,
+                }
+            })
+            .collect();
+        let mut scene = Scene {
+            objects,
+            ..Scene::default()
+        };
+        // rays from around the triangles towards a point among them
+        let rays = (0..2000)
+            .map(|_| {
+                let o = random_point(&mut rng, 15.);
+                let target = random_point(&mut rng, 10.);
+                (o, sub3!(target, o))
+            })
+            .collect::<Vec<_>>();
+        let trace = |scene: &Scene| {
+            rays.iter()
+                .map(|(o, d)| {
+                    let (object, t) = closest_intersection(scene, o, d, EPS, f64::INFINITY);
+                    let index = object.map(|object| {
+                        scene
+                            .objects
+                            .iter()
+                            .position(|other| std::ptr::eq(object, other))
+                            .unwrap()
+                    });
+                    (index, t, any_intersection(scene, o, d, EPS, 0.5))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        scene.bvh = None;
+        let brute_force = trace(&scene);
+        scene.build_bvh();
+        let with_bvh = trace(&scene);
+        assert!(brute_force.iter().filter(|hit| hit.0.is_some()).count() > 200);
+        for (ray, (expected, actual)) in rays.iter().zip(brute_force.iter().zip(&with_bvh)) {
+            assert_eq!(expected, actual, "ray {ray:?}");
+        }
     }
 }
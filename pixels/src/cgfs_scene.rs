@@ -1,11 +1,25 @@
 #![allow(dead_code)]
 
+use crate::cgfs_rasterization::{to_subpixels, Attribute};
+use crate::pipeline::{Uniforms, VertexInput, VertexShader};
 use nalgebra::{Matrix3, Matrix3x4, Matrix4, Point3, Vector3, Vector4};
+use std::sync::OnceLock;
+
+/// Specular exponent of every model's surface, as in the book.
+const SPECULAR: f64 = 50.;
 
 pub struct Model<'a> {
     pub vertices: &'a [Vector4<f64>],
     pub triangles: &'a [(usize, usize, usize)],
     pub triangle_colors: &'a [(f64, f64, f64)],
+    /// Unit normals of the model's surface.
+    pub normals: &'a [Vector3<f64>],
+    /// Indices of the normals at the vertices of every triangle.
+    pub triangle_normals: &'a [(usize, usize, usize)],
+    /// Texture coordinates on the model's surface, with v pointing up in the texture.
+    pub uvs: &'a [(f64, f64)],
+    /// Indices of the texture coordinates at the vertices of every triangle.
+    pub triangle_uvs: &'a [(usize, usize, usize)],
 }
 
 const CUBE: Model = Model {
@@ -47,8 +61,97 @@ const CUBE: Model = Model {
         (0., 1., 1.),
         (0., 1., 1.),
     ],
+    normals: &[
+        Vector3::new(0., 0., 1.),
+        Vector3::new(1., 0., 0.),
+        Vector3::new(0., 0., -1.),
+        Vector3::new(-1., 0., 0.),
+        Vector3::new(0., 1., 0.),
+        Vector3::new(0., -1., 0.),
+    ],
+    triangle_normals: &[
+        (0, 0, 0),
+        (0, 0, 0),
+        (1, 1, 1),
+        (1, 1, 1),
+        (2, 2, 2),
+        (2, 2, 2),
+        (3, 3, 3),
+        (3, 3, 3),
+        (4, 4, 4),
+        (4, 4, 4),
+        (5, 5, 5),
+        (5, 5, 5),
+    ],
+    // every face shows the whole texture upright, as seen from the outside
+    uvs: &[(0., 1.), (1., 1.), (1., 0.), (0., 0.)],
+    triangle_uvs: &[
+        (0, 1, 2),
+        (0, 2, 3),
+        (0, 1, 2),
+        (0, 2, 3),
+        (0, 1, 2),
+        (0, 2, 3),
+        (0, 1, 2),
+        (0, 2, 3),
+        (0, 1, 2),
+        (0, 2, 3),
+        (0, 1, 2),
+        (0, 2, 3),
+    ],
 };
 
+/// A green unit sphere made of `DIVS` bands of `DIVS` quads each, generated as in the book.
+/// Its normals are the positions of its vertices, so it looks smooth when they are interpolated.
+/// The texture is wrapped around it by longitude and latitude, with a seam of duplicated coordinates.
+fn sphere() -> &'static Model<'static> {
+    const DIVS: usize = 15;
+    static SPHERE: OnceLock<Model<'static>> = OnceLock::new();
+    SPHERE.get_or_init(|| {
+        let mut vertices = Vec::new();
+        for d in 0..=DIVS {
+            let y = 2. * d as f64 / DIVS as f64 - 1.;
+            let r = (1. - y * y).max(0.).sqrt();
+            for i in 0..DIVS {
+                let alpha = 2. * std::f64::consts::PI * i as f64 / DIVS as f64;
+                vertices.push(Vector4::new(r * alpha.cos(), y, r * alpha.sin(), 1.));
+            }
+        }
+        let mut uvs = Vec::new();
+        for d in 0..=DIVS {
+            for i in 0..=DIVS {
+                uvs.push((i as f64 / DIVS as f64, d as f64 / DIVS as f64));
+            }
+        }
+        let mut triangles = Vec::new();
+        let mut triangle_uvs = Vec::new();
+        for d in 0..DIVS {
+            for i in 0..DIVS {
+                let i0 = d * DIVS + i;
+                let i1 = (d + 1) * DIVS + (i + 1) % DIVS;
+                let i2 = d * DIVS + (i + 1) % DIVS;
+                triangles.push((i0, i1, i2));
+                triangles.push((i0, i0 + DIVS, i1));
+                // the texture coordinates do not wrap around
+                let t0 = d * (DIVS + 1) + i;
+                let t1 = (d + 1) * (DIVS + 1) + i + 1;
+                triangle_uvs.push((t0, t1, t0 + 1));
+                triangle_uvs.push((t0, t0 + DIVS + 1, t1));
+            }
+        }
+        let normals = vertices.iter().map(|v| v.xyz()).collect::<Vec<_>>();
+        Model {
+            vertices: vertices.leak(),
+            triangle_colors: vec![(0., 1., 0.); triangles.len()].leak(),
+            triangle_normals: triangles.clone().leak(),
+            triangles: triangles.leak(),
+            normals: normals.leak(),
+            uvs: uvs.leak(),
+            triangle_uvs: triangle_uvs.leak(),
+        }
+    })
+}
+
 pub fn homogeneous_rotation(x: f64, y: f64, z: f64) -> Matrix4<f64> {
     Matrix4::new_rotation(Vector3::new(x, y, z))
 }
@@ -83,7 +186,8 @@ pub fn homogeneous_viewport_to_homogeneous_canvas(s_x: f64, s_y: f64) -> Matrix3
     Matrix3::from_diagonal(&Vector3::new(s_x, s_y, 1.))
 }
 
-/// Project a homogenous point in space to a homogenous point on the canvas.
+/// Project a homogenous point in space to a point on the canvas in fixed point with its depth.
+/// The fixed-point coordinates keep `SUBPIXEL_BITS` fractional bits of the projected position.
 pub fn homogeneous_3d_to_canvas(
     v_w: f64,
     v_h: f64,
@@ -93,12 +197,104 @@ pub fn homogeneous_3d_to_canvas(
     point: Vector4<f64>,
 ) -> (i64, i64, f64) {
     (
-        ((point[0] * d * c_w) / (point[2] * v_w)) as i64,
-        ((point[1] * d * c_h) / (point[2] * v_h)) as i64,
+        to_subpixels((point[0] * d * c_w) / (point[2] * v_w)),
+        to_subpixels((point[1] * d * c_h) / (point[2] * v_h)),
         point[2],
     )
 }
 
+/// Light sources of the rasterized scene, positioned in world space.
+/// Directional lights are given by the direction towards the light.
+pub enum Light {
+    Ambient {
+        intensity: f64,
+    },
+    Directional {
+        intensity: f64,
+        direction: Vector3<f64>,
+    },
+    Point {
+        intensity: f64,
+        position: Vector3<f64>,
+    },
+}
+
+impl Light {
+    /// Moves the light into the camera space given by the inverse camera transform.
+    pub fn to_camera_space(&self, camera_m_inv: &Matrix4<f64>) -> Light {
+        match *self {
+            Light::Ambient { intensity } => Light::Ambient { intensity },
+            Light::Directional {
+                intensity,
+                direction,
+            } => Light::Directional {
+                intensity,
+                direction: (camera_m_inv * direction.to_homogeneous()).xyz(),
+            },
+            Light::Point {
+                intensity,
+                position,
+            } => Light::Point {
+                intensity,
+                position: (camera_m_inv * Point3::from(position).to_homogeneous()).xyz(),
+            },
+        }
+    }
+}
+
+/// The lights from chapter 13 of the book.
+pub fn default_lights() -> Vec<Light> {
+    vec![
+        Light::Ambient { intensity: 0.2 },
+        Light::Directional {
+            intensity: 0.2,
+            direction: Vector3::new(-1., 0., 1.),
+        },
+        Light::Point {
+            intensity: 0.6,
+            position: Vector3::new(-3., 2., -10.),
+        },
+    ]
+}
+
+/// Computes the intensity of the light at the point `p` with the normal `n`, both in camera space.
+/// The lights must be in camera space as well, the camera itself is at the origin.
+pub fn compute_lighting(lights: &[Light], p: &Vector3<f64>, n: &Vector3<f64>) -> f64 {
+    let n = n.normalize();
+    let v = -p;
+    let mut i = 0.;
+    for light in lights {
+        let (intensity, l) = match light {
+            Light::Ambient { intensity } => {
+                i += intensity;
+                continue;
+            }
+            Light::Directional {
+                intensity,
+                direction,
+            } => (intensity, *direction),
+            Light::Point {
+                intensity,
+                position,
+            } => (intensity, position - p),
+        };
+
+        // Diffuse
+        let n_dot_l = n.dot(&l);
+        if n_dot_l > 0. {
+            i += intensity * n_dot_l / l.norm();
+        }
+
+        // Specular
+        let r = 2. * n * n_dot_l - l;
+        let r_dot_v = r.dot(&v);
+        if r_dot_v > 0. {
+            i += intensity * (r_dot_v / (r.norm() * v.norm())).powf(SPECULAR);
+        }
+    }
+    i
+}
+
 /// A instance of a model.
 pub struct Instance<'a> {
     pub model: &'a Model<'a>,
@@ -162,44 +358,87 @@ impl<'a> Instance<'a> {
         })
     }
 
-    pub fn project(
-        &'a self,
+    /// Values shared by the shaders of the instance's vertices and fragments.
+    pub fn uniforms<'l>(
+        &self,
         camera_m_inv: &Matrix4<f64>,
+        lights: &'l [Light],
+        time: f64,
+    ) -> Uniforms<'l> {
+        let model_view = camera_m_inv * self.transform();
+        // normals are transformed by the inverse transpose, so that they stay perpendicular to the surface
+        let normal_matrix = model_view
+            .fixed_slice::<3, 3>(0, 0)
+            .into_owned()
+            .try_inverse()
+            .unwrap_or_else(Matrix3::identity)
+            .transpose();
+        Uniforms {
+            model_view,
+            normal_matrix,
+            lights,
+            time,
+        }
+    }
+
+    /// Runs the vertex shader on the corners of the model's triangle with the index `idx`.
+    fn shade_triangle<S: VertexShader>(
+        &self,
+        idx: usize,
+        shader: &S,
+        uniforms: &Uniforms,
+    ) -> [ClipVertex<S::Varying>; 3] {
+        let model = self.model;
+        let (t, n, u) = (
+            model.triangles[idx],
+            model.triangle_normals[idx],
+            model.triangle_uvs[idx],
+        );
+        [(t.0, n.0, u.0), (t.1, n.1, u.1), (t.2, n.2, u.2)].map(|(t, n, u)| {
+            let vertex = VertexInput {
+                position: model.vertices[t],
+                normal: model.normals[n],
+                uv: model.uvs[u],
+                color: model.triangle_colors[idx],
+            };
+            shader.shade(uniforms, &vertex)
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn project<'s, S: VertexShader>(
+        &'a self,
+        shader: &'s S,
+        uniforms: &'s Uniforms<'s>,
         v_w: f64,
         v_h: f64,
         d: f64,
         c_w: f64,
         c_h: f64,
-    ) -> Triangles<'a> {
-        macro_rules! homogenize {
-            ($a: expr) => {
-                homogeneous_3d_to_canvas(v_w, v_h, d, c_w, c_h, $a)
-            };
-        }
-        let m = camera_m_inv * self.transform();
-        let projected = self
-            .model
-            .vertices
-            .iter()
-            .map(|x| homogenize!(m * x))
-            .collect::<Vec<_>>();
+    ) -> Triangles<'a, 's, S> {
         Triangles::Full {
             instance: self,
-            projected,
+            shader,
+            uniforms,
             next_idx: 0,
+            viewport_data: (v_w, v_h, d, c_w, c_h),
         }
     }
 
-    pub fn project_and_clip(
+    #[allow(clippy::too_many_arguments)]
+    pub fn project_and_clip<'s, S: VertexShader>(
         &'a self,
         camera_m_inv: &Matrix4<f64>,
+        shader: &'s S,
+        uniforms: &'s Uniforms<'s>,
         v_w: f64,
         v_h: f64,
         d: f64,
         c_w: f64,
         c_h: f64,
-    ) -> Triangles<'a> {
-        let radius = self.radius_from_origin();
+    ) -> Triangles<'a, 's, S> {
+        // the vertex shader may move the vertices out of the model's bounding sphere
+        let radius = self.radius_from_origin() + shader.max_displacement() * self.scale.amax();
         let clipping_planes = vec![
             (Vector3::new(0., 0., 1.), d),
             (Vector3::new(d, 0., v_w / 2.).normalize(), 0.),
@@ -220,41 +459,20 @@ impl<'a> Instance<'a> {
             .fold(f64::INFINITY, |a, b| a.min(b));
         // println!("worst: {worst_position}");
         // println!("radius: {radius}");
-        let m = camera_m_inv * self.transform();
-        macro_rules! homogenize {
-            ($a: expr) => {
-                homogeneous_3d_to_canvas(v_w, v_h, d, c_w, c_h, $a)
-            };
-        }
         if worst_position <= -radius {
             // println!("Empty");
             Triangles::Empty
         } else if worst_position >= radius {
             // println!("Full");
-            let projected = self
-                .model
-                .vertices
-                .iter()
-                .map(|x| homogenize!(m * x))
-                .collect::<Vec<_>>();
-            Triangles::Full {
-                instance: self,
-                projected,
-                next_idx: 0,
-            }
+            self.project(shader, uniforms, v_w, v_h, d, c_w, c_h)
         } else {
             // println!("Partial");
-            let projected = self
-                .model
-                .vertices
-                .iter()
-                .map(|x| m * x)
-                .collect::<Vec<_>>();
             Triangles::Partial {
                 instance: self,
-                projected,
+                shader,
+                uniforms,
                 next_idx: 0,
-                clipping_planes: clipping_planes,
+                clipping_planes,
                 viewport_data: (v_w, v_h, d, c_w, c_h),
                 temp: vec![],
             }
@@ -263,35 +481,36 @@ impl<'a> Instance<'a> {
 }
 
 /// A triangle on the canvas.
-/// Contains 3 points with inverse depths and a color.
-type Triangle = (
-    (i64, i64, f64),
-    (i64, i64, f64),
-    (i64, i64, f64),
-    (f64, f64, f64),
-);
-
-/// Iterator over triangles of an instance.
+/// Contains 3 points with inverse depths and the varyings of its vertices.
+type Triangle<V> = ((i64, i64, f64), (i64, i64, f64), (i64, i64, f64), [V; 3]);
+
+/// A vertex as returned by the vertex shader, its homogeneous position in camera space and its varyings.
+type ClipVertex<V> = (Vector4<f64>, V);
+
+/// Iterator over triangles of an instance, with its vertices shaded by the vertex shader.
 /// Variant depends on whether the instance is clipped fully, partially, or not.
-pub enum Triangles<'a> {
+pub enum Triangles<'a, 's, S: VertexShader> {
     Empty,
     Partial {
         instance: &'a Instance<'a>,
-        projected: Vec<Vector4<f64>>,
+        shader: &'s S,
+        uniforms: &'s Uniforms<'s>,
         next_idx: usize,
         clipping_planes: Vec<(Vector3<f64>, f64)>,
         viewport_data: (f64, f64, f64, f64, f64),
-        temp: Vec<Triangle>,
+        temp: Vec<Triangle<S::Varying>>,
     },
     Full {
         instance: &'a Instance<'a>,
-        projected: Vec<(i64, i64, f64)>,
+        shader: &'s S,
+        uniforms: &'s Uniforms<'s>,
         next_idx: usize,
+        viewport_data: (f64, f64, f64, f64, f64),
     },
 }
 
-impl<'a> Iterator for Triangles<'a> {
-    type Item = Triangle;
+impl<'a, 's, S: VertexShader> Iterator for Triangles<'a, 's, S> {
+    type Item = Triangle<S::Varying>;
     fn next(&mut self) -> Option<Self::Item> {
         match self {
             // instance is fully clipped => display nothing
@@ -299,7 +518,8 @@ impl<'a> Iterator for Triangles<'a> {
             // instance is partially clipped => clip each triangle separately
             Self::Partial {
                 instance,
-                projected,
+                shader,
+                uniforms,
                 next_idx,
                 clipping_planes,
                 viewport_data: v,
@@ -317,20 +537,28 @@ impl<'a> Iterator for Triangles<'a> {
                             // some triangles remain => generate next one
                             let prev_idx = *next_idx;
                             let mut tris = {
-                                let (a, b, c) = instance.model.triangles[prev_idx];
-                                let (a, b, c) = (projected[a], projected[b], projected[c]);
-                                vec![(a, b, c, instance.model.triangle_colors[prev_idx])]
+                                let [a, b, c] =
+                                    instance.shade_triangle(prev_idx, *shader, uniforms);
+                                vec![(a, b, c)]
                             };
                             // clip triangle candidates using the clipping planes
                             for (p, d) in clipping_planes {
                                 let mut new_tris = vec![];
                                 macro_rules! point_dot {
                                     ($a: expr) => {
-                                        (Point3::from_homogeneous($a).unwrap() - Point3::origin())
+                                        (Point3::from_homogeneous($a.0).unwrap() - Point3::origin())
                                             .dot(p)
                                     };
                                 }
-                                for (v1, v2, v3, col) in tris {
+                                // the point where the edge from `a` to `b` crosses the clipping plane
+                                let intersect =
+                                    |a: ClipVertex<S::Varying>, b: ClipVertex<S::Varying>| {
+                                        let delta = Point3::from_homogeneous(b.0).unwrap()
+                                            - Point3::from_homogeneous(a.0).unwrap();
+                                        let t = (*d - point_dot!(a)) / delta.dot(p);
+                                        (a.0 + t * delta.to_homogeneous(), a.1.lerp(&b.1, t))
+                                    };
+                                for (v1, v2, v3) in tris {
                                     let f1 = point_dot!(v1) >= *d;
                                     let f2 = point_dot!(v2) >= *d;
                                     let f3 = point_dot!(v3) >= *d;
@@ -349,21 +577,9 @@ impl<'a> Iterator for Triangles<'a> {
                                                 (_, true) => (v2, v3, v1),
                                                 _ => (v3, v1, v2),
                                             };
-                                            let mid1 = {
-                                                let delta = Point3::from_homogeneous(back1)
-                                                    .unwrap()
-                                                    - Point3::from_homogeneous(front).unwrap();
-                                                let t = (*d - point_dot!(front)) / delta.dot(p);
-                                                front + t * delta.to_homogeneous()
-                                            };
-                                            let mid2 = {
-                                                let delta = Point3::from_homogeneous(back2)
-                                                    .unwrap()
-                                                    - Point3::from_homogeneous(front).unwrap();
-                                                let t = (*d - point_dot!(front)) / delta.dot(p);
-                                                front + t * delta.to_homogeneous()
-                                            };
-                                            new_tris.push((front, mid1, mid2, col));
+                                            let mid1 = intersect(front, back1);
+                                            let mid2 = intersect(front, back2);
+                                            new_tris.push((front, mid1, mid2));
                                         }
                                         2 => {
                                             // two vertices in front of the clipping plane
@@ -374,25 +590,15 @@ impl<'a> Iterator for Triangles<'a> {
                                                 (_, false) => (v2, v3, v1),
                                                 _ => (v3, v1, v2),
                                             };
-                                            let mid1 = {
-                                                let delta = Point3::from_homogeneous(back).unwrap()
-                                                    - Point3::from_homogeneous(front1).unwrap();
-                                                let t = (*d - point_dot!(front1)) / delta.dot(p);
-                                                front1 + t * delta.to_homogeneous()
-                                            };
-                                            let mid2 = {
-                                                let delta = Point3::from_homogeneous(back).unwrap()
-                                                    - Point3::from_homogeneous(front2).unwrap();
-                                                let t = (*d - point_dot!(front2)) / delta.dot(p);
-                                                front2 + t * delta.to_homogeneous()
-                                            };
-                                            new_tris.push((mid1, front1, mid2, col));
-                                            new_tris.push((front1, front2, mid2, col));
+                                            let mid1 = intersect(front1, back);
+                                            let mid2 = intersect(front2, back);
+                                            new_tris.push((mid1, front1, mid2));
+                                            new_tris.push((front1, front2, mid2));
                                         }
                                         _ => {
                                             // all vertices in front of the clipping plance
                                             // keep the triangle as is
-                                            new_tris.push((v1, v2, v3, col));
+                                            new_tris.push((v1, v2, v3));
                                         }
                                     };
                                 }
@@ -400,12 +606,12 @@ impl<'a> Iterator for Triangles<'a> {
                             }
                             *next_idx += 1;
                             // enqueue the generated triangles to be returned
-                            temp.extend(tris.into_iter().map(|(v1, v2, v3, col)| {
+                            temp.extend(tris.into_iter().map(|(v1, v2, v3)| {
                                 (
-                                    homogeneous_3d_to_canvas(v.0, v.1, v.2, v.3, v.4, v1),
-                                    homogeneous_3d_to_canvas(v.0, v.1, v.2, v.3, v.4, v2),
-                                    homogeneous_3d_to_canvas(v.0, v.1, v.2, v.3, v.4, v3),
-                                    col,
+                                    homogeneous_3d_to_canvas(v.0, v.1, v.2, v.3, v.4, v1.0),
+                                    homogeneous_3d_to_canvas(v.0, v.1, v.2, v.3, v.4, v2.0),
+                                    homogeneous_3d_to_canvas(v.0, v.1, v.2, v.3, v.4, v3.0),
+                                    [v1.1, v2.1, v3.1],
                                 )
                             }));
                             self.next()
@@ -416,22 +622,23 @@ impl<'a> Iterator for Triangles<'a> {
             // instance is not clipped => display all triangles
             Self::Full {
                 instance,
-                projected,
+                shader,
+                uniforms,
                 next_idx,
+                viewport_data: v,
             } => {
                 if *next_idx >= instance.model.triangles.len() {
                     // all triangles of the model were already visited => finish returning
                     None
                 } else {
                     // some triangles remain => generate next one
-                    let prev_idx = *next_idx;
-                    let (t0, t1, t2) = instance.model.triangles[prev_idx];
+                    let [a, b, c] = instance.shade_triangle(*next_idx, *shader, uniforms);
                     *next_idx += 1;
                     Some((
-                        projected[t0],
-                        projected[t1],
-                        projected[t2],
-                        instance.model.triangle_colors[prev_idx],
+                        homogeneous_3d_to_canvas(v.0, v.1, v.2, v.3, v.4, a.0),
+                        homogeneous_3d_to_canvas(v.0, v.1, v.2, v.3, v.4, b.0),
+                        homogeneous_3d_to_canvas(v.0, v.1, v.2, v.3, v.4, c.0),
+                        [a.1, b.1, c.1],
                     ))
                 }
             }
@@ -463,10 +670,34 @@ pub fn simple_scene<'a>() -> Vec<Instance<'a>> {
     ]
 }
 
+/// A scene with a sphere between two cubes, showing off the smooth shading.
+pub fn sphere_scene<'a>() -> Vec<Instance<'a>> {
+    vec![
+        Instance::new(
+            &CUBE,
+            Vector3::new(0.75, 0.75, 0.75),
+            homogeneous_rotation(0., 0.5, 0.),
+            Vector3::new(-2.5, 0., 7.),
+        ),
+        Instance::new(
+            sphere(),
+            Vector3::new(1.5, 1.5, 1.5),
+            Matrix4::identity(),
+            Vector3::new(0., 0., 7.),
+        ),
+        Instance::new(
+            &CUBE,
+            Vector3::new(0.75, 0.75, 0.75),
+            homogeneous_rotation(0., -0.5, 0.),
+            Vector3::new(2.5, 0., 7.),
+        ),
+    ]
+}
+
 /// A scene with a grid of `subdiv` by `subdiv` cubes.
 pub fn cube_grid<'a>(subdivs: i32) -> Vec<Instance<'a>> {
     (0..2 * subdivs)
-        .map(|i| {
+        .flat_map(|i| {
             (0..2 * subdivs).map(move |j| {
                 Instance::new(
                     &CUBE,
@@ -480,14 +711,13 @@ pub fn cube_grid<'a>(subdivs: i32) -> Vec<Instance<'a>> {
                 )
             })
         })
-        .flatten()
         .collect()
 }
 
 /// A scene with a checkerboard grid of `subdiv` by `subdiv` cubes.
 pub fn cube_checkerboard<'a>(subdivs: i32) -> Vec<Instance<'a>> {
     (0..2 * subdivs)
-        .map(|i| {
+        .flat_map(|i| {
             (0..2 * subdivs).map(move |j| {
                 if i % 2 == j % 2 {
                     Some(Instance::new(
@@ -506,15 +736,13 @@ pub fn cube_checkerboard<'a>(subdivs: i32) -> Vec<Instance<'a>> {
             })
         })
         .flatten()
-        .flatten()
         .collect()
 }
 
 /// Determine whether the camera is looking at the front or at the back of the triangle.
 pub fn cull_triangle(p: &(i64, i64, f64), q: &(i64, i64, f64), r: &(i64, i64, f64)) -> bool {
     let m = Matrix3::new(
-        1 as f64, p.0 as f64, p.1 as f64, 1 as f64, q.0 as f64, q.1 as f64, 1 as f64, r.0 as f64,
-        r.1 as f64,
+        1., p.0 as f64, p.1 as f64, 1., q.0 as f64, q.1 as f64, 1., r.0 as f64, r.1 as f64,
     );
     m.determinant() > 0.
 }
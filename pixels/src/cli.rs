@@ -0,0 +1,413 @@
+use crate::cgfs_scene::{self, Instance};
+use clap::builder::RangedU64ValueParser;
+use clap::{Parser, Subcommand, ValueEnum};
+use rand::Rng;
+use std::path::PathBuf;
+
+/// Command line interface of the program.
+/// Without an output file, the chosen scene is displayed in a window.
+#[derive(Parser)]
+#[command(about = "Graphics experiments on top of the pixels frame buffer")]
+pub struct Cli {
+    /// Width of the canvas in pixels.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = crate::WIDTH,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub width: usize,
+    /// Height of the canvas in pixels.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = crate::HEIGHT,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub height: usize,
+    /// Render offline into this PNG file instead of opening a window.
+    /// Files ending in `.exr` or `.hdr` receive the colors before tone mapping.
+    /// With more than one frame, the frame number is appended to the file name.
+    #[arg(short, long, global = true)]
+    pub output: Option<PathBuf>,
+    /// Number of frames to render offline, for animated scenes.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 1,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub frames: usize,
+    /// Frames per second of the offline rendered sequence.
+    #[arg(long, global = true, default_value_t = 30.)]
+    pub fps: f64,
+    /// Tone mapping operator bringing the colors into the displayable range.
+    #[arg(long, value_enum, global = true, default_value_t = ToneMap::Clamp)]
+    pub tone_map: ToneMap,
+    /// Exposure in stops, every stop doubles the brightness before tone mapping.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 0.,
+        allow_negative_numbers = true
+    )]
+    pub exposure: f64,
+    /// Encode the tone mapped colors with the sRGB transfer function, for scenes lit in linear light.
+    #[arg(long, global = true)]
+    pub srgb: bool,
+    /// Scene to draw, the second rasterization scene by default.
+    #[command(subcommand)]
+    pub scene: Option<Command>,
+}
+
+/// Scenes that can be drawn.
+#[derive(Subcommand, Clone)]
+pub enum Command {
+    /// The raytracing scene (CGFS chapters 2 through 5).
+    Raytrace {
+        /// Scene description in RON format, the scene from the book is used if omitted.
+        #[arg(long)]
+        scene: Option<PathBuf>,
+        /// Trace the models of a rasterization scene instead.
+        #[arg(long, value_enum, conflicts_with = "scene")]
+        models: Option<Models>,
+        /// Number of shadow rays cast towards every area light, overrides the scene's setting.
+        #[arg(long)]
+        shadow_samples: Option<usize>,
+        /// Radius of the camera's lens, overrides the scene's setting.
+        #[arg(long)]
+        aperture: Option<f64>,
+        /// Distance to the plane in focus, overrides the scene's setting.
+        #[arg(long)]
+        focal_distance: Option<f64>,
+        /// Render with global illumination by path tracing, the samples accumulate over the frames.
+        #[arg(long)]
+        path_tracing: bool,
+        /// Number of samples per pixel for antialiasing, with path tracing the number traced in every frame.
+        #[arg(long, default_value_t = 1)]
+        samples: i32,
+        /// Reconstruction filter weighting the antialiasing samples, or spreading the paths of the path tracer.
+        #[arg(long, value_enum, default_value_t = Filter::Box)]
+        filter: Filter,
+    },
+    /// The first rasterization scene (CGFS chapters 6 through 9).
+    Raster,
+    /// The second rasterization scene (CGFS chapters 10 through 15).
+    Scene {
+        /// Models to rasterize.
+        #[arg(long, value_enum, default_value_t = Models::Checkerboard)]
+        models: Models,
+        /// Shading of the models, cycled with L in the window.
+        #[arg(long, value_enum, default_value_t = Shading::Unlit)]
+        shading: Shading,
+        /// PNG or JPEG image textured onto every face of the models in place of their colors.
+        #[arg(long)]
+        texture: Option<PathBuf>,
+        /// Sampling of the texture, cycled with T in the window.
+        #[arg(long, value_enum, default_value_t = Sampling::Bilinear)]
+        sampling: Sampling,
+        /// Sample the texture from its mipmaps to reduce aliasing in the distance, toggled with M in the window.
+        #[arg(long)]
+        mipmaps: bool,
+        /// Shaders replacing the standard ones, for effects.
+        #[arg(long, value_enum, default_value_t = Effect::None)]
+        effect: Effect,
+        /// Algorithm filling the triangles, toggled with R in the window.
+        #[arg(long, value_enum, default_value_t = Rasterizer::EdgeSpans)]
+        rasterizer: Rasterizer,
+    },
+    /// The Mandelbrot set with a naive approach.
+    MandelNaive {
+        /// Maximum number of iterations.
+        #[arg(long, default_value_t = 100)]
+        iterations: i64,
+    },
+    /// The Mandelbrot set with smooth coloring and antialiasing.
+    MandelSmooth {
+        /// Maximum number of iterations.
+        #[arg(long, default_value_t = 100)]
+        iterations: i64,
+        /// Real part of the viewport center.
+        #[arg(long, default_value_t = 0., allow_negative_numbers = true)]
+        x: f64,
+        /// Imaginary part of the viewport center.
+        #[arg(long, default_value_t = 0., allow_negative_numbers = true)]
+        y: f64,
+        /// Zoom level as a base 2 logarithm of the viewport size, negative values zoom in.
+        #[arg(long, default_value_t = 0., allow_negative_numbers = true)]
+        scale: f64,
+        /// Number of samples per pixel.
+        #[arg(long, default_value_t = 0)]
+        samples: i32,
+    },
+    /// Measure the performance of the renderers instead of drawing a scene.
+    Bench {
+        #[command(subcommand)]
+        benchmark: Benchmark,
+    },
+}
+
+/// Available benchmarks.
+#[derive(Subcommand, Clone)]
+pub enum Benchmark {
+    /// Raytrace a checkerboard of cubes with and without the bounding volume hierarchy.
+    Bvh {
+        /// Size of the checkerboard, it consists of 24 * size^2 triangles.
+        #[arg(long, default_value_t = 10)]
+        size: i32,
+    },
+    /// Rasterize a checkerboard of cubes span by span and in parallel tiles.
+    Raster {
+        /// Size of the checkerboard, it consists of 24 * size^2 triangles.
+        #[arg(long, default_value_t = 6)]
+        size: i32,
+        /// Number of frames drawn with each rasterizer, the average time is reported.
+        #[arg(long, default_value_t = 10)]
+        runs: u32,
+    },
+}
+
+/// Sets of model instances shared by the rasterizer and the raytracer.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Models {
+    /// Three cubes.
+    Simple,
+    /// A checkerboard grid of cubes.
+    Checkerboard,
+    /// A sphere between two cubes.
+    Spheres,
+}
+
+impl Models {
+    pub fn instances(&self) -> Vec<Instance<'static>> {
+        match self {
+            Models::Simple => cgfs_scene::simple_scene(),
+            Models::Checkerboard => cgfs_scene::cube_checkerboard(6),
+            Models::Spheres => cgfs_scene::sphere_scene(),
+        }
+    }
+}
+
+/// Shading of the rasterized models (CGFS chapter 13).
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Shading {
+    /// The models' colors without any lighting.
+    Unlit,
+    /// Lighting computed once per triangle.
+    Flat,
+    /// Lighting computed at the vertices and interpolated.
+    Gouraud,
+    /// Lighting computed at every pixel with interpolated normals.
+    Phong,
+}
+
+impl Shading {
+    /// The shading that follows when cycling through them.
+    pub fn next(&self) -> Shading {
+        match self {
+            Shading::Unlit => Shading::Flat,
+            Shading::Flat => Shading::Gouraud,
+            Shading::Gouraud => Shading::Phong,
+            Shading::Phong => Shading::Unlit,
+        }
+    }
+}
+
+/// Effects of the rasterizer's shaders.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Effect {
+    /// The standard shaders.
+    None,
+    /// Models swaying in a wave.
+    Wave,
+    /// Cel shading with outlines.
+    Toon,
+}
+
+/// Algorithms filling the rasterized triangles.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Rasterizer {
+    /// Row by row, the span from the first to the last pixel inside all three edge functions,
+    /// one triangle after another.
+    EdgeSpans,
+    /// Every pixel of the bounding boxes tested against all three edge functions, in tiles drawn in parallel.
+    EdgeTiled,
+}
+
+impl Rasterizer {
+    /// The other rasterizer.
+    pub fn next(&self) -> Rasterizer {
+        match self {
+            Rasterizer::EdgeSpans => Rasterizer::EdgeTiled,
+            Rasterizer::EdgeTiled => Rasterizer::EdgeSpans,
+        }
+    }
+}
+
+/// Sampling of the rasterizer's textures (CGFS chapter 14).
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Sampling {
+    /// The closest texel.
+    Nearest,
+    /// The four closest texels blended, with mipmaps also the two closest levels.
+    Bilinear,
+}
+
+impl Sampling {
+    /// The sampling that follows when cycling through them.
+    pub fn next(&self) -> Sampling {
+        match self {
+            Sampling::Nearest => Sampling::Bilinear,
+            Sampling::Bilinear => Sampling::Nearest,
+        }
+    }
+}
+
+/// Reconstruction filters for antialiasing.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Filter {
+    /// Equal weights within the pixel.
+    Box,
+    /// Weights falling linearly to zero one pixel away from the center.
+    Tent,
+    /// Gaussian weights with the standard deviation of half a pixel.
+    Gaussian,
+}
+
+impl Filter {
+    /// Distance from the pixel center in pixels, up to which the samples are taken.
+    pub fn radius(&self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.,
+            Filter::Gaussian => 1.5,
+        }
+    }
+
+    /// Weight of a sample at the offset (dx, dy) from the pixel center.
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        match self {
+            Filter::Box => 1.,
+            Filter::Tent => (1. - dx.abs()).max(0.) * (1. - dy.abs()).max(0.),
+            Filter::Gaussian => (-(dx * dx + dy * dy) / (2. * 0.5 * 0.5)).exp(),
+        }
+    }
+
+    /// A random offset from the pixel center, distributed like the weights within the radius,
+    /// so that the samples drawn this way are averaged with equal weights.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> (f64, f64) {
+        match self {
+            Filter::Box => (rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5),
+            // the sum of two uniform offsets falls off linearly
+            Filter::Tent => (
+                rng.gen::<f64>() + rng.gen::<f64>() - 1.,
+                rng.gen::<f64>() + rng.gen::<f64>() - 1.,
+            ),
+            // Box-Muller transform, retried outside of the radius
+            Filter::Gaussian => loop {
+                let r = 0.5 * (-2. * (1. - rng.gen::<f64>()).ln()).sqrt();
+                let phi = 2. * std::f64::consts::PI * rng.gen::<f64>();
+                let (dx, dy) = (r * phi.cos(), r * phi.sin());
+                if dx.abs() <= self.radius() && dy.abs() <= self.radius() {
+                    break (dx, dy);
+                }
+            },
+        }
+    }
+}
+
+/// Tone mapping operators.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ToneMap {
+    /// Colors outside of [0, 1] are clipped.
+    Clamp,
+    /// Reinhard's operator `c / (1 + c)`, compressing the highlights.
+    Reinhard,
+    /// An approximation of the ACES filmic curve.
+    Aces,
+}
+
+impl ToneMap {
+    /// Maps an exposed color channel into [0, 1].
+    pub fn apply(&self, c: f64) -> f64 {
+        match self {
+            ToneMap::Clamp => c.clamp(0., 1.),
+            ToneMap::Reinhard => c.max(0.) / (1. + c.max(0.)),
+            // fit by Krzysztof Narkowicz
+            ToneMap::Aces => {
+                let c = c.max(0.);
+                (c * (2.51 * c + 0.03) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0., 1.)
+            }
+        }
+    }
+}
+
+/// Scene parameters that can also be changed while the scene is displayed.
+#[derive(Clone, PartialEq)]
+pub struct Params {
+    pub iterations: i64,
+    /// Viewport center and zoom level.
+    pub position: [f64; 3],
+    pub samples: i32,
+    pub shading: Shading,
+    pub sampling: Sampling,
+    pub mipmaps: bool,
+    pub rasterizer: Rasterizer,
+}
+
+impl Default for Params {
+    /// Parameters left alone by the scenes that do not use them.
+    fn default() -> Params {
+        Params {
+            iterations: 0,
+            position: [0., 0., 0.],
+            samples: 0,
+            shading: Shading::Unlit,
+            sampling: Sampling::Bilinear,
+            mipmaps: false,
+            rasterizer: Rasterizer::EdgeSpans,
+        }
+    }
+}
+
+impl Command {
+    /// Initial parameters of the scene, as given on the command line.
+    pub fn params(&self) -> Params {
+        match *self {
+            Command::Raytrace { samples, .. } => Params {
+                samples,
+                ..Default::default()
+            },
+            Command::MandelNaive { iterations } => Params {
+                iterations,
+                ..Default::default()
+            },
+            Command::MandelSmooth {
+                iterations,
+                x,
+                y,
+                scale,
+                samples,
+            } => Params {
+                iterations,
+                position: [x, y, scale],
+                samples,
+                ..Default::default()
+            },
+            Command::Scene {
+                shading,
+                sampling,
+                mipmaps,
+                rasterizer,
+                ..
+            } => Params {
+                shading,
+                sampling,
+                mipmaps,
+                rasterizer,
+                ..Default::default()
+            },
+            _ => Params::default(),
+        }
+    }
+}
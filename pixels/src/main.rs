@@ -1,8 +1,16 @@
+use accumulator::Accumulator;
+use canvas::Canvas;
+use clap::Parser;
+use cli::{Cli, Command, Effect, Filter, Models, Rasterizer, Sampling, Shading};
 use nalgebra::Complex;
 use pixels::{Pixels, SurfaceTexture};
+use rand::Rng;
 use rayon::prelude::*;
 use std::ops::Index;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use texture::MipMap;
+use tiles::TileRenderer;
+use tone_mapping::ToneMapping;
 use winit::{
     dpi::LogicalSize,
     event::{Event, VirtualKeyCode},
@@ -12,82 +20,118 @@ use winit::{
 };
 use winit_input_helper::WinitInputHelper;
 
+// default canvas size
 // raytracing: 240x240
 // rasterization: 600x600
 const WIDTH: usize = 1200;
 const HEIGHT: usize = 1200;
+mod accumulator;
+mod bench;
+mod bvh;
+mod canvas;
 mod cgfs_rasterization;
 mod cgfs_raytracing;
 mod cgfs_scene;
+mod cli;
 mod mandel;
+mod offline;
+mod pipeline;
+mod shaders;
+mod texture;
+mod tiles;
+mod tone_mapping;
 
 /// Draws the raytracing scene.
 /// Based on the chapters 2 through 5 of the book Computer Graphics from Scratch.
-fn draw_scene_raytracing(frame: &mut [u8], start: &Instant) {
-    let _time = start.elapsed().as_secs_f64();
-    // the camera's position
-    let o = (0., 0., 0.);
-    // let o = (_time.sin(), _time.cos() + 1., 0.);
-    // the viewport data: (width, height, distance from camera)
-    let v = (1., 1., 1.);
-    for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-        let x = (i % WIDTH) as f64 - WIDTH as f64 / 2.;
-        let y = HEIGHT as f64 / 2. - (i / WIDTH) as f64;
-
-        let d = cgfs_raytracing::canvas_to_viewport(&v, x, y, WIDTH as f64, HEIGHT as f64);
-        let color = cgfs_raytracing::trace_ray(&o, &d, 1., f64::INFINITY, 5);
-        let color = (
-            color.0.clamp(0., 1.),
-            color.1.clamp(0., 1.),
-            color.2.clamp(0., 1.),
-        );
+/// With more than one sample per pixel, the samples are weighted by the reconstruction filter
+/// and spread over the camera's lens for depth of field.
+/// The canvas is drawn in parallel tiles.
+fn draw_scene_raytracing(
+    canvas: &mut Canvas,
+    _time: f64,
+    scene: &cgfs_raytracing::Scene,
+    samples: usize,
+    filter: Filter,
+    tiles: &mut TileRenderer,
+) {
+    let (width, height) = (canvas.width, canvas.height);
+    // trace a ray through the canvas point (x, y) and the lens point
+    let trace = |x: f64, y: f64, lens: (f64, f64)| {
+        let (o, d) = scene.camera.ray(x, y, width as f64, height as f64, lens);
+        cgfs_raytracing::trace_ray(scene, &o, &d, 1., f64::INFINITY, 5)
+    };
+    tiles.draw(canvas, |i, j| {
+        let x = i as f64 - width as f64 / 2.;
+        let y = height as f64 / 2. - j as f64;
 
-        let rgba = [
-            (255.99 * color.0) as u8,
-            (255.99 * color.1) as u8,
-            (255.99 * color.2) as u8,
-            0xff,
-        ];
-        pixel.copy_from_slice(&rgba);
-    }
-}
+        let color = if samples <= 1 {
+            trace(x, y, (0., 0.))
+        } else {
+            let (mut sum, mut weights) = ((0., 0., 0.), 0.);
+            // spread the samples over the filter's support around the pixel
+            // and over the lens, with another pair of bases so they do not correlate
+            let lens = mandel::halton_2d::<5, 7>();
+            for ((dx, dy), lens) in mandel::halton_2d::<2, 3>().zip(lens).take(samples) {
+                let dx = (2. * dx - 1.) * filter.radius();
+                let dy = (2. * dy - 1.) * filter.radius();
+                let w = filter.weight(dx, dy);
+                let color = trace(x + dx, y + dy, lens);
+                sum = (
+                    sum.0 + w * color.0,
+                    sum.1 + w * color.1,
+                    sum.2 + w * color.2,
+                );
+                weights += w;
+            }
+            (sum.0 / weights, sum.1 / weights, sum.2 / weights)
+        };
 
-/// Colors the pixel (x, y) on the canvas with the goven color.
-fn put_pixel(x: i64, y: i64, color: &(f64, f64, f64), canvas: &mut [u8]) {
-    let x = (x + WIDTH as i64 / 2).clamp(0, WIDTH as i64 - 1) as usize;
-    let y = (HEIGHT as i64 / 2 - y).clamp(0, HEIGHT as i64 - 1) as usize;
-    let start = 4 * (y * WIDTH + x);
-    canvas[start] = (255.99 * color.0.clamp(0., 1.)) as u8;
-    canvas[start + 1] = (255.99 * color.1.clamp(0., 1.)) as u8;
-    canvas[start + 2] = (255.99 * color.2.clamp(0., 1.)) as u8;
+        [color.0 as f32, color.1 as f32, color.2 as f32]
+    });
 }
 
-/// Colors the pixel (x, y) on the canvas with the goven color, if the new depth is closer than the old one.
-fn put_pixel_depth(
-    x: i64,
-    y: i64,
-    z_inv: f64,
-    color: &(f64, f64, f64),
-    canvas: &mut [u8],
-    depth_buffer: &mut [f64],
+/// Draws the raytracing scene with global illumination.
+/// Traces `samples` paths through every pixel, spread around it by the filter,
+/// and adds them to the ones from the previous frames.
+fn draw_scene_path_tracing(
+    canvas: &mut Canvas,
+    scene: &cgfs_raytracing::Scene,
+    accumulator: &mut Accumulator,
+    samples: usize,
+    filter: Filter,
 ) {
-    let x = (x + WIDTH as i64 / 2).clamp(0, WIDTH as i64 - 1) as usize;
-    let y = (HEIGHT as i64 / 2 - y).clamp(0, HEIGHT as i64 - 1) as usize;
-    let pos = y * WIDTH + x;
-    if depth_buffer[pos] < z_inv {
-        let start = 4 * pos;
-        canvas[start] = (255.99 * color.0.clamp(0., 1.)) as u8;
-        canvas[start + 1] = (255.99 * color.1.clamp(0., 1.)) as u8;
-        canvas[start + 2] = (255.99 * color.2.clamp(0., 1.)) as u8;
-        depth_buffer[pos] = z_inv;
-    }
+    let (width, height) = (canvas.width, canvas.height);
+    accumulator.fit(width, height);
+    accumulator
+        .sum
+        .par_chunks_exact_mut(width)
+        .enumerate()
+        .for_each(|(j, row)| {
+            let mut rng = rand::thread_rng();
+            for (i, sum) in row.iter_mut().enumerate() {
+                for _ in 0..samples {
+                    // a random point around the pixel spread by the filter, which also smooths the edges
+                    let (dx, dy) = filter.sample(&mut rng);
+                    let x = i as f64 - width as f64 / 2. + dx;
+                    let y = height as f64 / 2. - j as f64 + dy;
+                    let lens = (rng.gen(), rng.gen());
+                    let (o, d) = scene.camera.ray(x, y, width as f64, height as f64, lens);
+                    let color = cgfs_raytracing::trace_path(scene, &o, &d, &mut rng);
+                    sum.0 += color.0;
+                    sum.1 += color.1;
+                    sum.2 += color.2;
+                }
+            }
+        });
+    accumulator.samples += samples;
+    accumulator.draw(canvas);
 }
 
 /// Draws the first rasterization scene.
 /// Based on the chapters 6 through 9 of the book Computer Graphics from Scratch.
-fn draw_scene_rasterization(frame: &mut [u8], start: &Instant) {
-    let _time = start.elapsed().as_secs_f64();
-    frame.fill(0xff);
+fn draw_scene_rasterization(canvas: &mut Canvas, _time: f64) {
+    let (width, height) = (canvas.width, canvas.height);
+    canvas.frame.fill([1.; 3]);
     if false {
         if false {
             cgfs_rasterization::draw_filled_triangle(
@@ -96,7 +140,7 @@ fn draw_scene_rasterization(frame: &mut [u8], start: &Instant) {
                 cgfs_rasterization::TRIANGLE_POINTS.index(2),
                 &(0.4, 1., 0.2),
                 |x, y, c| {
-                    put_pixel(x, y, c, frame);
+                    canvas.put_pixel(x, y, c);
                 },
             );
         } else {
@@ -107,7 +151,7 @@ fn draw_scene_rasterization(frame: &mut [u8], start: &Instant) {
                 &(0.4, 1., 0.2),
                 &(1., 0.5, 0.),
                 |x, y, c| {
-                    put_pixel(x, y, c, frame);
+                    canvas.put_pixel(x, y, c);
                 },
             );
         }
@@ -117,7 +161,7 @@ fn draw_scene_rasterization(frame: &mut [u8], start: &Instant) {
                 cgfs_rasterization::TRIANGLE_POINTS.index(*p1),
                 &(0., 0., 0.),
                 |x, y, c| {
-                    put_pixel(x, y, c, frame);
+                    canvas.put_pixel(x, y, c);
                 },
             )
         }
@@ -130,20 +174,20 @@ fn draw_scene_rasterization(frame: &mut [u8], start: &Instant) {
                 &cgfs_rasterization::project_vertex(
                     &v,
                     &o,
-                    WIDTH as f64,
-                    HEIGHT as f64,
+                    width as f64,
+                    height as f64,
                     cgfs_rasterization::CUBE_POINTS.index(*p0),
                 ),
                 &cgfs_rasterization::project_vertex(
                     &v,
                     &o,
-                    WIDTH as f64,
-                    HEIGHT as f64,
+                    width as f64,
+                    height as f64,
                     cgfs_rasterization::CUBE_POINTS.index(*p1),
                 ),
                 c,
                 |x, y, c| {
-                    put_pixel(x, y, c, frame);
+                    canvas.put_pixel(x, y, c);
                 },
             )
         }
@@ -152,114 +196,329 @@ fn draw_scene_rasterization(frame: &mut [u8], start: &Instant) {
 
 /// Draws the second rasterization scene.
 /// Based on the chapters 10 through 15 of the book Computer Graphics from Scratch.
-fn draw_scene_rasterization_scene(frame: &mut [u8], start: &Instant, _mul: i64, _scale: i32) {
+fn draw_scene_rasterization_scene(
+    canvas: &mut Canvas,
+    time: f64,
+    models: Models,
+    effect: Effect,
+    texture: Option<&MipMap>,
+    params: &cli::Params,
+) {
     // reset frame to white
-    frame.fill(0xff);
-    // create depth buffer with infinite distance
-    // (depth buffer holds inverse of distance)
-    let mut depth_buffer = vec![0.; WIDTH * HEIGHT];
+    canvas.frame.fill([1.; 3]);
     // create and position the camera
     let mut camera = cgfs_scene::Camera::default();
-    let rot = 0.3 * (start.elapsed().as_secs_f64() * 0.1).sin();
+    let rot = 0.3 * (time * 0.1).sin();
     camera.rotation = cgfs_scene::homogeneous_rotation(0., rot, 0.);
-    // precompute the inverse of the camera transform
-    let camera_m_inv = camera.inverse_transform();
-    // extract viewport data from the camera
-    let (v_w, v_h, d) = (
-        camera.perspective[0],
-        camera.perspective[1],
-        camera.perspective[2],
-    );
     // select a scene to render
-    let scene = match 1 {
-        0 => cgfs_scene::simple_scene(),
-        _ => cgfs_scene::cube_checkerboard(6),
+    let scene = models.instances();
+    let lights = cgfs_scene::default_lights();
+    // select the shaders
+    let vertex_shader = shaders::StandardVertex {
+        shading: params.shading,
     };
-    // render all instances in the scene
-    for instance in scene {
-        for (p0, p1, p2, color) in
-            instance.project_and_clip(&camera_m_inv, v_w, v_h, d, WIDTH as f64, HEIGHT as f64)
-        {
-            // cull triangle
-            if cgfs_scene::cull_triangle(&p0, &p1, &p2) {
-                continue;
-            }
-            cgfs_rasterization::draw_filled_triangle_with_depth(
-                &p0,
-                &p1,
-                &p2,
-                &color,
-                |x, y, z, c| {
-                    put_pixel_depth(x, y, z, c, frame, &mut depth_buffer);
-                },
-            )
-        }
+    let fragment_shader = shaders::StandardFragment {
+        shading: params.shading,
+        texture,
+        sampling: params.sampling,
+        mipmaps: params.mipmaps,
+    };
+    // draw the scene with the shaders of the effect
+    match effect {
+        Effect::None => pipeline::draw(
+            canvas,
+            &camera,
+            &scene,
+            &lights,
+            time,
+            params.rasterizer,
+            &vertex_shader,
+            &fragment_shader,
+        ),
+        Effect::Wave => pipeline::draw(
+            canvas,
+            &camera,
+            &scene,
+            &lights,
+            time,
+            params.rasterizer,
+            &shaders::Wave(vertex_shader),
+            &fragment_shader,
+        ),
+        Effect::Toon => pipeline::draw(
+            canvas,
+            &camera,
+            &scene,
+            &lights,
+            time,
+            params.rasterizer,
+            &vertex_shader,
+            &shaders::Toon { bands: 4. },
+        ),
     }
 }
 
 /// Draws the mandelbrot set with a naive approach.
-fn draw_mandelbrot_naive(frame: &mut [u8], max_iters: i64) {
+fn draw_mandelbrot_naive(canvas: &mut Canvas, max_iters: i64, srgb: bool) {
+    let (width, height) = (canvas.width, canvas.height);
     let max_iters = max_iters.max(0) as u64;
-    for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-        let x = 4. * (i % WIDTH) as f64 / WIDTH as f64 - 2.;
-        let y = 4. * (i / WIDTH) as f64 / HEIGHT as f64 - 2.;
+    for (i, pixel) in canvas.frame.iter_mut().enumerate() {
+        let x = 4. * (i % width) as f64 / width as f64 - 2.;
+        let y = 4. * (i / width) as f64 / height as f64 - 2.;
         let iters = mandel::mandelbrot_naive(Complex::new(x, y), max_iters);
         let rgba = mandel::gradient_bw(iters as f64, max_iters as f64);
-        pixel.copy_from_slice(&rgba);
+        *pixel = canvas::from_rgba8(&rgba, srgb);
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn mandelbrot_smooth_calculate_pixel(
     i: usize,
-    pixel: &mut [u8],
+    pixel: &mut [f32; 3],
+    width: usize,
+    height: usize,
     max_iters: u64,
     pos: &[f64; 3],
     samples: i32,
+    srgb: bool,
 ) {
     if samples <= 1 {
         // determine the complex number represented by the pixel
-        let x = (4. * ((i % WIDTH) as f64 + 0.5) / WIDTH as f64 - 2.) * 2_f64.powf(pos[2]) + pos[0];
-        let y = (4. * ((i / WIDTH) as f64 + 1. / 3.) / HEIGHT as f64 - 2.) * 2_f64.powf(pos[2])
+        let x = (4. * ((i % width) as f64 + 0.5) / width as f64 - 2.) * 2_f64.powf(pos[2]) + pos[0];
+        let y = (4. * ((i / width) as f64 + 1. / 3.) / height as f64 - 2.) * 2_f64.powf(pos[2])
             + pos[1];
         // calculate the smoothed number of iterations
         let iters = mandel::mandelbrot_smooth(Complex::new(x, y), max_iters);
         // assign a color to the number of iterations
         let rgba = mandel::gradient_bbmw(iters, max_iters as f64);
-        pixel.copy_from_slice(&rgba);
+        *pixel = canvas::from_rgba8(&rgba, srgb);
     } else {
         let mut iters = 0.;
         // calculate the smoothed number of iteration for many samples
         for (dx, dy) in mandel::halton_2d::<2, 3>().take(samples as usize) {
             let x =
-                (4. * ((i % WIDTH) as f64 + dx) / WIDTH as f64 - 2.) * 2_f64.powf(pos[2]) + pos[0];
+                (4. * ((i % width) as f64 + dx) / width as f64 - 2.) * 2_f64.powf(pos[2]) + pos[0];
             let y =
-                (4. * ((i / WIDTH) as f64 + dy) / HEIGHT as f64 - 2.) * 2_f64.powf(pos[2]) + pos[1];
+                (4. * ((i / width) as f64 + dy) / height as f64 - 2.) * 2_f64.powf(pos[2]) + pos[1];
             iters += mandel::mandelbrot_smooth(Complex::new(x, y), max_iters);
         }
         // assign a color to the number of iterations
         let rgba = mandel::gradient_bbmw(iters / samples as f64, max_iters as f64);
-        pixel.copy_from_slice(&rgba);
+        *pixel = canvas::from_rgba8(&rgba, srgb);
     }
 }
 
 /// Draws the mandelbrot set with smooth coloring and antialiasing.
 /// Supports moving and scaling using the numpad.
-fn draw_mandelbrot_smooth_moving(frame: &mut [u8], max_iters: i64, pos: &[f64; 3], samples: i32) {
+fn draw_mandelbrot_smooth_moving(
+    canvas: &mut Canvas,
+    max_iters: i64,
+    pos: &[f64; 3],
+    samples: i32,
+    srgb: bool,
+) {
+    let (width, height) = (canvas.width, canvas.height);
     let max_iters = max_iters.max(0) as u64;
-    frame
-        .par_chunks_exact_mut(4)
+    canvas
+        .frame
+        .par_iter_mut()
         .enumerate()
-        .map(|(i, pixel)| mandelbrot_smooth_calculate_pixel(i, pixel, max_iters, pos, samples))
+        .map(|(i, pixel)| {
+            mandelbrot_smooth_calculate_pixel(
+                i, pixel, width, height, max_iters, pos, samples, srgb,
+            )
+        })
         .collect()
 }
 
+/// Flies the raytracer's camera with the keyboard and the mouse, `dt` is the time since the last update.
+/// WASD moves the camera, space and left shift move it up and down,
+/// arrow keys or dragging the mouse turn it and scrolling zooms in and out.
+/// Returns whether the camera has changed.
+fn fly_camera(camera: &mut cgfs_raytracing::Camera, input: &WinitInputHelper, dt: f64) -> bool {
+    // movement in units and turning in degrees per second
+    let (speed, turn_speed) = (2., 90.);
+    let axis = |positive, negative| {
+        (input.key_held(positive) as i32 - input.key_held(negative) as i32) as f64
+    };
+    let forward = axis(VirtualKeyCode::W, VirtualKeyCode::S) * speed * dt;
+    let right = axis(VirtualKeyCode::D, VirtualKeyCode::A) * speed * dt;
+    let up = axis(VirtualKeyCode::Space, VirtualKeyCode::LShift) * speed * dt;
+    let mut yaw = axis(VirtualKeyCode::Right, VirtualKeyCode::Left) * turn_speed * dt;
+    let mut pitch = axis(VirtualKeyCode::Up, VirtualKeyCode::Down) * turn_speed * dt;
+    if input.mouse_held(0) {
+        let (dx, dy) = input.mouse_diff();
+        yaw += 0.2 * dx as f64;
+        pitch -= 0.2 * dy as f64;
+    }
+    let zoom = input.scroll_diff() as f64;
+
+    if forward == 0. && right == 0. && up == 0. && yaw == 0. && pitch == 0. && zoom == 0. {
+        return false;
+    }
+    camera.fly(forward, right, up);
+    camera.turn(yaw, pitch);
+    camera.fov = (camera.fov - 5. * zoom).clamp(10., 150.);
+    true
+}
+
+/// Draws the chosen scene onto the canvas, `srgb` tells whether its colors are encoded with sRGB afterwards.
+#[allow(clippy::too_many_arguments)]
+fn draw_scene(
+    scene: &Command,
+    canvas: &mut Canvas,
+    time: f64,
+    params: &cli::Params,
+    srgb: bool,
+    raytracing_scene: &cgfs_raytracing::Scene,
+    texture: Option<&MipMap>,
+    accumulator: &mut Accumulator,
+    tiles: &mut TileRenderer,
+) {
+    match scene {
+        Command::Raytrace {
+            path_tracing: true,
+            filter,
+            ..
+        } => draw_scene_path_tracing(
+            canvas,
+            raytracing_scene,
+            accumulator,
+            params.samples.max(1) as usize,
+            *filter,
+        ),
+        Command::Raytrace { filter, .. } => draw_scene_raytracing(
+            canvas,
+            time,
+            raytracing_scene,
+            params.samples.max(1) as usize,
+            *filter,
+            tiles,
+        ),
+        Command::Raster => draw_scene_rasterization(canvas, time),
+        Command::Scene { models, effect, .. } => {
+            draw_scene_rasterization_scene(canvas, time, *models, *effect, texture, params)
+        }
+        Command::MandelNaive { .. } => draw_mandelbrot_naive(canvas, params.iterations, srgb),
+        Command::MandelSmooth { .. } => draw_mandelbrot_smooth_moving(
+            canvas,
+            params.iterations,
+            &params.position,
+            params.samples,
+            srgb,
+        ),
+        Command::Bench { .. } => unreachable!("benchmarks do not draw on the window"),
+    }
+}
+
 fn main() {
+    let cli = Cli::parse();
+    let scene = cli.scene.clone().unwrap_or(Command::Scene {
+        models: Models::Checkerboard,
+        shading: Shading::Unlit,
+        texture: None,
+        sampling: Sampling::Bilinear,
+        mipmaps: false,
+        effect: Effect::None,
+        rasterizer: Rasterizer::EdgeSpans,
+    });
+    // multiple auxiliary parameters
+    let mut params = scene.params();
+    // world of the raytracer
+    let mut raytracing_scene = match &scene {
+        Command::Raytrace {
+            scene: Some(path), ..
+        } => cgfs_raytracing::Scene::load(path, cli.srgb).unwrap_or_else(|e| {
+            eprintln!("Loading scene {} failed: {e}", path.display());
+            std::process::exit(1);
+        }),
+        Command::Raytrace {
+            models: Some(models),
+            ..
+        } => cgfs_raytracing::Scene::from_instances(&models.instances()),
+        _ => cgfs_raytracing::Scene::default(),
+    };
+    if let Command::Raytrace {
+        shadow_samples,
+        aperture,
+        focal_distance,
+        ..
+    } = scene
+    {
+        if let Some(samples) = shadow_samples {
+            raytracing_scene.shadow_samples = samples;
+        }
+        if let Some(aperture) = aperture {
+            raytracing_scene.camera.aperture = aperture;
+        }
+        if let Some(distance) = focal_distance {
+            raytracing_scene.camera.focal_distance = distance;
+        }
+    }
+
+    // texture of the rasterized models
+    let texture = match &scene {
+        Command::Scene {
+            texture: Some(path),
+            ..
+        } => Some(MipMap::load(path, cli.srgb).unwrap_or_else(|e| {
+            eprintln!("Loading texture {} failed: {e}", path.display());
+            std::process::exit(1);
+        })),
+        _ => None,
+    };
+
+    if let Command::Bench { benchmark } = &scene {
+        bench::run(benchmark, cli.width, cli.height);
+        return;
+    }
+
+    // samples of the path traced scene
+    let mut accumulator = Accumulator::default();
+    // raytraced frames are drawn whole offline and progressively in the window
+    let mut tiles = TileRenderer::new(cli.output.is_none().then_some(Duration::from_millis(30)));
+    // conversion of the drawn colors into the displayed ones
+    let mut tone_mapping = ToneMapping {
+        operator: cli.tone_map,
+        exposure: cli.exposure,
+        srgb: cli.srgb,
+    };
+
+    // render offline if an output file is given
+    if let Some(output) = &cli.output {
+        if let Err(e) = offline::render(
+            output,
+            cli.width,
+            cli.height,
+            cli.frames,
+            cli.fps,
+            &tone_mapping,
+            |canvas, time| {
+                draw_scene(
+                    &scene,
+                    canvas,
+                    time,
+                    &params,
+                    tone_mapping.srgb,
+                    &raytracing_scene,
+                    texture.as_ref(),
+                    &mut accumulator,
+                    &mut tiles,
+                )
+            },
+        ) {
+            eprintln!("Rendering failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // main event loop
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
     // window that contains the framebuffer
     let window = {
-        let size = LogicalSize::new(WIDTH as u32, HEIGHT as u32);
+        let size = LogicalSize::new(cli.width as u32, cli.height as u32);
         WindowBuilder::new()
             .with_title("Hello Pixels")
             .with_inner_size(size)
@@ -272,29 +531,33 @@ fn main() {
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture).unwrap()
+        Pixels::new(cli.width as u32, cli.height as u32, surface_texture).unwrap()
     };
-    // multiple auxiliary parameters
+    // current size of the framebuffer
+    let (mut width, mut height) = (cli.width, cli.height);
+    // colors drawn by the renderers, before tone mapping
+    let mut colors = vec![[0.; 3]; width * height];
     let start_time = Instant::now();
-    let mut param = 0_i64;
-    let mut param2 = [0., 0., 0.];
-    let mut samples = 0_i32;
     let mut prev_time = Instant::now();
+    let mut prev_input_time = Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
         // draw a new frame
         if let Event::RedrawRequested(_) = event {
-            // select scene to draw
-            match 2 {
-                0 => draw_scene_raytracing(pixels.get_frame(), &start_time),
-                1 => draw_scene_rasterization(pixels.get_frame(), &start_time),
-                2 => {
-                    draw_scene_rasterization_scene(pixels.get_frame(), &start_time, param, samples)
-                }
-                3 => draw_mandelbrot_naive(pixels.get_frame(), param),
-                4 => draw_mandelbrot_smooth_moving(pixels.get_frame(), param, &param2, samples),
-                _ => (),
-            }
+            // draw the selected scene
+            let time = start_time.elapsed().as_secs_f64();
+            draw_scene(
+                &scene,
+                &mut Canvas::new(&mut colors, width, height),
+                time,
+                &params,
+                tone_mapping.srgb,
+                &raytracing_scene,
+                texture.as_ref(),
+                &mut accumulator,
+                &mut tiles,
+            );
+            tone_mapping.apply(&colors, pixels.get_frame());
 
             // display drawing time and frames per second
             if true {
@@ -323,48 +586,91 @@ fn main() {
                 *control_flow = ControlFlow::Exit;
                 return;
             }
+            let previous_params = params.clone();
             if input.key_pressed(VirtualKeyCode::NumpadAdd) {
-                param += 1;
-                println!("param = {param}");
+                params.iterations += 1;
+                println!("iterations = {}", params.iterations);
             }
             if input.key_pressed(VirtualKeyCode::NumpadSubtract) {
-                param -= 1;
-                println!("param = {param}");
+                params.iterations -= 1;
+                println!("iterations = {}", params.iterations);
             }
             if input.key_pressed(VirtualKeyCode::Numpad4) {
-                param2[0] -= 0.1 * (2_f64).powf(param2[2]);
-                println!("x = {}", param2[0]);
+                params.position[0] -= 0.1 * (2_f64).powf(params.position[2]);
+                println!("x = {}", params.position[0]);
             }
             if input.key_pressed(VirtualKeyCode::Numpad6) {
-                param2[0] += 0.1 * (2_f64).powf(param2[2]);
-                println!("x = {}", param2[0]);
+                params.position[0] += 0.1 * (2_f64).powf(params.position[2]);
+                println!("x = {}", params.position[0]);
             }
             if input.key_pressed(VirtualKeyCode::Numpad8) {
-                param2[1] -= 0.1 * (2_f64).powf(param2[2]);
-                println!("y = {}", param2[1]);
+                params.position[1] -= 0.1 * (2_f64).powf(params.position[2]);
+                println!("y = {}", params.position[1]);
             }
             if input.key_pressed(VirtualKeyCode::Numpad5) {
-                param2[1] += 0.1 * (2_f64).powf(param2[2]);
-                println!("y = {}", param2[1]);
+                params.position[1] += 0.1 * (2_f64).powf(params.position[2]);
+                println!("y = {}", params.position[1]);
             }
             if input.key_pressed(VirtualKeyCode::Numpad7) {
-                param2[2] += 0.1;
-                println!("scale = {}", param2[2]);
+                params.position[2] += 0.1;
+                println!("scale = {}", params.position[2]);
             }
             if input.key_pressed(VirtualKeyCode::Numpad9) {
-                param2[2] -= 0.1;
-                println!("scale = {}", param2[2]);
+                params.position[2] -= 0.1;
+                println!("scale = {}", params.position[2]);
             }
             if input.key_pressed(VirtualKeyCode::Numpad1) {
-                samples -= 1;
-                println!("samples = {}", samples);
+                params.samples -= 1;
+                println!("samples = {}", params.samples);
             }
             if input.key_pressed(VirtualKeyCode::Numpad2) {
-                samples += 1;
-                println!("samples = {}", samples);
+                params.samples += 1;
+                println!("samples = {}", params.samples);
+            }
+            if input.key_pressed(VirtualKeyCode::L) {
+                params.shading = params.shading.next();
+                println!("shading = {:?}", params.shading);
+            }
+            if input.key_pressed(VirtualKeyCode::T) {
+                params.sampling = params.sampling.next();
+                println!("sampling = {:?}", params.sampling);
             }
+            if input.key_pressed(VirtualKeyCode::M) {
+                params.mipmaps = !params.mipmaps;
+                println!("mipmaps = {}", params.mipmaps);
+            }
+            if input.key_pressed(VirtualKeyCode::R) {
+                params.rasterizer = params.rasterizer.next();
+                println!("rasterizer = {:?}", params.rasterizer);
+            }
+            if input.key_pressed(VirtualKeyCode::PageUp) {
+                tone_mapping.exposure += 0.5;
+                println!("exposure = {}", tone_mapping.exposure);
+            }
+            if input.key_pressed(VirtualKeyCode::PageDown) {
+                tone_mapping.exposure -= 0.5;
+                println!("exposure = {}", tone_mapping.exposure);
+            }
+            let mut changed = params != previous_params;
+            if let Command::Raytrace { .. } = scene {
+                let dt = prev_input_time.elapsed().as_secs_f64();
+                changed |= fly_camera(&mut raytracing_scene.camera, &input, dt);
+            }
+            if changed {
+                // the image has to be drawn anew
+                accumulator.reset();
+                tiles.reset();
+            }
+            prev_input_time = Instant::now();
             if let Some(size) = input.window_resized() {
-                pixels.resize_surface(size.width, size.height);
+                // match the framebuffer to the new window size instead of stretching it
+                if size.width > 0 && size.height > 0 {
+                    pixels.resize_surface(size.width, size.height);
+                    pixels.resize_buffer(size.width, size.height);
+                    width = size.width as usize;
+                    height = size.height as usize;
+                    colors = vec![[0.; 3]; width * height];
+                }
             }
             // input was detected => redraw the window
             window.request_redraw();
@@ -0,0 +1,79 @@
+use crate::canvas::Canvas;
+use crate::tone_mapping::ToneMapping;
+use image::codecs::hdr::HdrEncoder;
+use image::{ColorType, ImageFormat, ImageResult, Rgb, Rgb32FImage};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Appends the frame number to the file name, e.g. `out.png` becomes `out_0007.png`.
+pub fn sequence_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}_{index:04}.{}", ext.to_string_lossy()),
+        None => format!("{stem}_{index:04}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Saves the colors of the canvas before tone mapping, as OpenEXR or Radiance HDR depending on the extension.
+/// Returns `false` without saving anything if the file is neither.
+fn save_hdr(path: &Path, colors: &[[f32; 3]], width: usize, height: usize) -> ImageResult<bool> {
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    match extension.to_lowercase().as_str() {
+        "exr" => {
+            let image = Rgb32FImage::from_fn(width as u32, height as u32, |x, y| {
+                Rgb(colors[y as usize * width + x as usize])
+            });
+            image.save_with_format(path, ImageFormat::OpenExr)?;
+        }
+        "hdr" => {
+            let pixels = colors.iter().map(|&c| Rgb(c)).collect::<Vec<_>>();
+            let file = BufWriter::new(File::create(path)?);
+            HdrEncoder::new(file).encode(&pixels, width, height)?;
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Renders frames into an in-memory buffer and saves each of them as a PNG file after tone mapping,
+/// or as an OpenEXR or Radiance HDR file with the colors as they were drawn.
+/// A single frame is saved to `path`, multiple frames are saved as a numbered sequence.
+/// `draw` receives the canvas and the time of the frame in seconds.
+pub fn render<Draw>(
+    path: &Path,
+    width: usize,
+    height: usize,
+    frames: usize,
+    fps: f64,
+    tone_mapping: &ToneMapping,
+    mut draw: Draw,
+) -> ImageResult<()>
+where
+    Draw: FnMut(&mut Canvas, f64),
+{
+    let mut colors = vec![[0.; 3]; width * height];
+    let mut frame = vec![0; 4 * width * height];
+    for i in 0..frames {
+        draw(&mut Canvas::new(&mut colors, width, height), i as f64 / fps);
+        let path = if frames == 1 {
+            path.to_path_buf()
+        } else {
+            sequence_path(path, i)
+        };
+        if !save_hdr(&path, &colors, width, height)? {
+            tone_mapping.apply(&colors, &mut frame);
+            image::save_buffer_with_format(
+                &path,
+                &frame,
+                width as u32,
+                height as u32,
+                ColorType::Rgba8,
+                ImageFormat::Png,
+            )?;
+        }
+        println!("Saved {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,273 @@
+use crate::canvas::Canvas;
+use crate::cgfs_rasterization::{self, Attribute};
+use crate::cgfs_scene::{self, Camera, Instance, Light};
+use crate::cli::Rasterizer;
+use nalgebra::{Matrix3, Matrix4, Vector3, Vector4};
+use rayon::prelude::*;
+
+/// A corner of a model's triangle, the input of the vertex shader.
+#[derive(Clone, Copy)]
+pub struct VertexInput {
+    /// Position in the model's space, in homogeneous coordinates.
+    pub position: Vector4<f64>,
+    pub normal: Vector3<f64>,
+    pub uv: (f64, f64),
+    /// Color of the triangle.
+    pub color: (f64, f64, f64),
+}
+
+/// Values shared by the shaders of all the vertices and fragments of an instance.
+pub struct Uniforms<'a> {
+    /// Transform from the model's space into camera space.
+    pub model_view: Matrix4<f64>,
+    /// Transform of the model's normals into camera space, the inverse transpose of `model_view`.
+    pub normal_matrix: Matrix3<f64>,
+    /// Lights of the scene in camera space.
+    pub lights: &'a [Light],
+    /// Time since the start in seconds.
+    pub time: f64,
+}
+
+/// Program run on every corner of the models' triangles.
+pub trait VertexShader {
+    /// Values passed on to the fragment shader.
+    /// They are interpolated when the triangles are clipped and across the triangles on the canvas.
+    type Varying: Attribute;
+
+    /// Transform the vertex into camera space.
+    /// Returns its homogeneous position in camera space and its varyings.
+    fn shade(&self, uniforms: &Uniforms, vertex: &VertexInput) -> (Vector4<f64>, Self::Varying);
+
+    /// How far the shader moves the vertices away from their place in the model at most,
+    /// the instances' bounding spheres are grown by it before they are clipped.
+    fn max_displacement(&self) -> f64 {
+        0.
+    }
+}
+
+/// A pixel covered by a triangle, the input of the fragment shader.
+pub struct Fragment<'a, V> {
+    pub x: i64,
+    pub y: i64,
+    /// Varyings interpolated at the pixel.
+    pub varying: &'a V,
+    /// Points of the triangle on the canvas in fixed point with their depths.
+    pub points: &'a [(i64, i64, f64); 3],
+    /// Varyings at the triangle's vertices.
+    pub vertices: &'a [V; 3],
+}
+
+impl<V: Attribute> Fragment<'_, V> {
+    /// Varyings interpolated at another pixel of the triangle's plane,
+    /// e.g. at a neighbouring one for the change of the varyings from pixel to pixel.
+    pub fn varying_at(&self, x: i64, y: i64) -> V {
+        let [p0, p1, p2] = self.points;
+        cgfs_rasterization::perspective_attributes(p0, p1, p2, self.vertices, x, y)
+    }
+}
+
+/// Program run on every pixel covered by the models' triangles.
+pub trait FragmentShader<V> {
+    /// Color of the fragment, or `None` to leave the pixel as it is.
+    fn shade(&self, uniforms: &Uniforms, fragment: &Fragment<V>) -> Option<(f64, f64, f64)>;
+}
+
+/// A projected triangle on the canvas with the varyings of its vertices and the uniforms of its instance.
+type ShadedTriangle<'u, V> = (&'u Uniforms<'u>, [(i64, i64, f64); 3], [V; 3]);
+
+/// Width and height of the tiles of the edge function rasterizer in pixels.
+const TILE_SIZE: i64 = 32;
+
+/// Draws the instances onto the canvas as seen by the camera, shaded by the shaders.
+/// The lights are given in world space.
+#[allow(clippy::too_many_arguments)]
+pub fn draw<VS, FS>(
+    canvas: &mut Canvas,
+    camera: &Camera,
+    instances: &[Instance],
+    lights: &[Light],
+    time: f64,
+    rasterizer: Rasterizer,
+    vertex_shader: &VS,
+    fragment_shader: &FS,
+) where
+    VS: VertexShader,
+    VS::Varying: Send + Sync,
+    FS: FragmentShader<VS::Varying> + Sync,
+{
+    // precompute the inverse of the camera transform
+    let camera_m_inv = camera.inverse_transform();
+    // extract viewport data from the camera
+    let (v_w, v_h, d) = (
+        camera.perspective[0],
+        camera.perspective[1],
+        camera.perspective[2],
+    );
+    // move the lights in front of the camera
+    let lights = lights
+        .iter()
+        .map(|light| light.to_camera_space(&camera_m_inv))
+        .collect::<Vec<_>>();
+    let uniforms = instances
+        .iter()
+        .map(|instance| instance.uniforms(&camera_m_inv, &lights, time))
+        .collect::<Vec<_>>();
+    // project all instances
+    let mut triangles = Vec::new();
+    for (instance, uniforms) in instances.iter().zip(&uniforms) {
+        for (p0, p1, p2, vertices) in instance.project_and_clip(
+            &camera_m_inv,
+            vertex_shader,
+            uniforms,
+            v_w,
+            v_h,
+            d,
+            canvas.width as f64,
+            canvas.height as f64,
+        ) {
+            // cull triangle
+            if !cgfs_scene::cull_triangle(&p0, &p1, &p2) {
+                triangles.push((uniforms, [p0, p1, p2], vertices));
+            }
+        }
+    }
+    match rasterizer {
+        Rasterizer::EdgeSpans => draw_spans(canvas, &triangles, fragment_shader),
+        Rasterizer::EdgeTiled => draw_tiled(canvas, &triangles, fragment_shader),
+    }
+}
+
+/// Fills the triangles one after another, span by span.
+fn draw_spans<V, FS>(canvas: &mut Canvas, triangles: &[ShadedTriangle<V>], fragment_shader: &FS)
+where
+    V: Attribute,
+    FS: FragmentShader<V>,
+{
+    // create depth buffer with infinite distance
+    // (depth buffer holds inverse of distance)
+    let mut depth_buffer = vec![0.; canvas.width * canvas.height];
+    for (uniforms, points, vertices) in triangles {
+        let [p0, p1, p2] = points;
+        cgfs_rasterization::draw_filled_triangle_with_depth(
+            p0,
+            p1,
+            p2,
+            vertices,
+            |x, y, z, varying| {
+                let fragment = Fragment {
+                    x,
+                    y,
+                    varying,
+                    points,
+                    vertices,
+                };
+                if let Some(color) = fragment_shader.shade(uniforms, &fragment) {
+                    canvas.put_pixel_depth(x, y, z, &color, &mut depth_buffer);
+                }
+            },
+        )
+    }
+}
+
+/// Fills the triangles with the edge function rasterizer. The canvas is split into tiles,
+/// the triangles are binned into the tiles their bounding boxes overlap, and the tiles are drawn in parallel,
+/// each with its own part of the frame and the depth buffer.
+fn draw_tiled<V, FS>(canvas: &mut Canvas, triangles: &[ShadedTriangle<V>], fragment_shader: &FS)
+where
+    V: Attribute + Send + Sync,
+    FS: FragmentShader<V> + Sync,
+{
+    let (width, height) = (canvas.width as i64, canvas.height as i64);
+    let (tiles_x, tiles_y) = (
+        (width + TILE_SIZE - 1) / TILE_SIZE,
+        (height + TILE_SIZE - 1) / TILE_SIZE,
+    );
+    // the canvas coordinates of a pixel in the given column and row of the frame
+    let to_canvas = |col: i64, row: i64| (col - width / 2, height / 2 - row);
+
+    // bin the triangles, keeping their order within every tile
+    let mut bins = vec![Vec::new(); (tiles_x * tiles_y) as usize];
+    for (i, (_, [p0, p1, p2], _)) in triangles.iter().enumerate() {
+        let ((x_min, y_min), (x_max, y_max)) = cgfs_rasterization::pixel_bounds(p0, p1, p2);
+        let (col_min, col_max) = (x_min + width / 2, x_max + width / 2);
+        let (row_min, row_max) = (height / 2 - y_max, height / 2 - y_min);
+        if col_max < 0 || row_max < 0 || col_min >= width || row_min >= height {
+            continue;
+        }
+        for ty in row_min.max(0) / TILE_SIZE..=row_max.min(height - 1) / TILE_SIZE {
+            for tx in col_min.max(0) / TILE_SIZE..=col_max.min(width - 1) / TILE_SIZE {
+                bins[(ty * tiles_x + tx) as usize].push(i);
+            }
+        }
+    }
+
+    // draw the tiles, starting from the frame's current content
+    let frame = &*canvas.frame;
+    let tiles = bins
+        .par_iter()
+        .enumerate()
+        .map(|(i, bin)| {
+            let (col, row) = (
+                i as i64 % tiles_x * TILE_SIZE,
+                i as i64 / tiles_x * TILE_SIZE,
+            );
+            let (tile_width, tile_height) =
+                (TILE_SIZE.min(width - col), TILE_SIZE.min(height - row));
+            let mut colors = (row..row + tile_height)
+                .flat_map(|r| {
+                    let start = (r * width + col) as usize;
+                    frame[start..start + tile_width as usize].iter().copied()
+                })
+                .collect::<Vec<_>>();
+            let mut depth_buffer = vec![0.; colors.len()];
+            let bounds = (
+                to_canvas(col, row + tile_height - 1),
+                to_canvas(col + tile_width - 1, row),
+            );
+            for &t in bin {
+                let (uniforms, points, vertices) = &triangles[t];
+                let [p0, p1, p2] = points;
+                cgfs_rasterization::draw_filled_triangle_half_space(
+                    p0,
+                    p1,
+                    p2,
+                    vertices,
+                    &bounds,
+                    |x, y, z, varying| {
+                        let pos =
+                            ((height / 2 - y - row) * tile_width + x + width / 2 - col) as usize;
+                        // skip the shading of hidden fragments
+                        if depth_buffer[pos] >= z {
+                            return;
+                        }
+                        let fragment = Fragment {
+                            x,
+                            y,
+                            varying,
+                            points,
+                            vertices,
+                        };
+                        if let Some(c) = fragment_shader.shade(uniforms, &fragment) {
+                            colors[pos] = [c.0 as f32, c.1 as f32, c.2 as f32];
+                            depth_buffer[pos] = z;
+                        }
+                    },
+                )
+            }
+            colors
+        })
+        .collect::<Vec<_>>();
+
+    // copy the tiles into the frame
+    for (i, colors) in tiles.into_iter().enumerate() {
+        let (col, row) = (
+            i as i64 % tiles_x * TILE_SIZE,
+            i as i64 / tiles_x * TILE_SIZE,
+        );
+        let tile_width = TILE_SIZE.min(width - col) as usize;
+        for (r, colors) in colors.chunks_exact(tile_width).enumerate() {
+            let start = (row as usize + r) * width as usize + col as usize;
+            canvas.frame[start..start + tile_width].copy_from_slice(colors);
+        }
+    }
+}
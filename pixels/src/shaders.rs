@@ -0,0 +1,140 @@
+use crate::cgfs_rasterization::Attribute;
+use crate::cgfs_scene::compute_lighting;
+use crate::cli::{Sampling, Shading};
+use crate::pipeline::{Fragment, FragmentShader, Uniforms, VertexInput, VertexShader};
+use crate::texture::MipMap;
+use nalgebra::{Point3, Vector3, Vector4};
+
+/// Varyings of the shaders below, the position and normal in camera space.
+#[derive(Clone, Copy)]
+pub struct Varyings {
+    pub position: Vector3<f64>,
+    pub normal: Vector3<f64>,
+    pub uv: (f64, f64),
+    pub color: (f64, f64, f64),
+    /// Light intensity at the vertex, for Gouraud shading.
+    pub intensity: f64,
+}
+
+impl Attribute for Varyings {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Varyings {
+            position: self.position.lerp(&other.position, t),
+            normal: self.normal.lerp(&other.normal, t),
+            uv: self.uv.lerp(&other.uv, t),
+            color: self.color.lerp(&other.color, t),
+            intensity: self.intensity.lerp(&other.intensity, t),
+        }
+    }
+}
+
+/// Transforms the vertices into camera space, and lights them for Gouraud shading.
+pub struct StandardVertex {
+    pub shading: Shading,
+}
+
+impl VertexShader for StandardVertex {
+    type Varying = Varyings;
+
+    fn shade(&self, uniforms: &Uniforms, vertex: &VertexInput) -> (Vector4<f64>, Varyings) {
+        let position = uniforms.model_view * vertex.position;
+        let p = Point3::from_homogeneous(position).unwrap().coords;
+        let normal = (uniforms.normal_matrix * vertex.normal).normalize();
+        let intensity = match self.shading {
+            Shading::Gouraud => compute_lighting(uniforms.lights, &p, &normal),
+            _ => 1.,
+        };
+        let varyings = Varyings {
+            position: p,
+            normal,
+            uv: vertex.uv,
+            color: vertex.color,
+            intensity,
+        };
+        (position, varyings)
+    }
+}
+
+/// Lights the pixels with the chosen shading and colors them with the models' colors or the texture.
+pub struct StandardFragment<'a> {
+    pub shading: Shading,
+    /// Texture replacing the models' colors.
+    pub texture: Option<&'a MipMap>,
+    pub sampling: Sampling,
+    pub mipmaps: bool,
+}
+
+impl FragmentShader<Varyings> for StandardFragment<'_> {
+    fn shade(&self, uniforms: &Uniforms, fragment: &Fragment<Varyings>) -> Option<(f64, f64, f64)> {
+        let v = fragment.varying;
+        let i = match self.shading {
+            Shading::Unlit => 1.,
+            // light the triangle's center with its average normal
+            Shading::Flat => {
+                let vertices = fragment.vertices;
+                let center = vertices.iter().map(|v| v.position).sum::<Vector3<f64>>() / 3.;
+                let normal = vertices.iter().map(|v| v.normal).sum();
+                compute_lighting(uniforms.lights, &center, &normal)
+            }
+            Shading::Gouraud => v.intensity,
+            Shading::Phong => compute_lighting(uniforms.lights, &v.position, &v.normal),
+        };
+        let c = match self.texture {
+            // the neighbouring pixels tell how much of the texture the pixel covers
+            Some(texture) => {
+                let uv_x = fragment.varying_at(fragment.x + 1, fragment.y).uv;
+                let uv_y = fragment.varying_at(fragment.x, fragment.y + 1).uv;
+                texture.sample(
+                    v.uv,
+                    (uv_x.0 - v.uv.0, uv_x.1 - v.uv.1),
+                    (uv_y.0 - v.uv.0, uv_y.1 - v.uv.1),
+                    self.sampling,
+                    self.mipmaps,
+                )
+            }
+            None => v.color,
+        };
+        Some((c.0 * i, c.1 * i, c.2 * i))
+    }
+}
+
+/// Sways the models sideways in a wave running up through them,
+/// before transforming them like the standard vertex shader.
+pub struct Wave(pub StandardVertex);
+
+/// How far the wave sways the models, in model space.
+const AMPLITUDE: f64 = 0.3;
+
+impl VertexShader for Wave {
+    type Varying = Varyings;
+
+    fn shade(&self, uniforms: &Uniforms, vertex: &VertexInput) -> (Vector4<f64>, Varyings) {
+        let mut vertex = *vertex;
+        vertex.position.x += AMPLITUDE * (2. * vertex.position.y - 3. * uniforms.time).sin();
+        self.0.shade(uniforms, &vertex)
+    }
+
+    fn max_displacement(&self) -> f64 {
+        AMPLITUDE
+    }
+}
+
+/// Cel shading: Phong lighting in a few flat bands,
+/// with dark outlines where the surface turns away from the camera.
+pub struct Toon {
+    /// Number of bands of light.
+    pub bands: f64,
+}
+
+impl FragmentShader<Varyings> for Toon {
+    fn shade(&self, uniforms: &Uniforms, fragment: &Fragment<Varyings>) -> Option<(f64, f64, f64)> {
+        let v = fragment.varying;
+        let n = v.normal.normalize();
+        if n.dot(&-v.position.normalize()) < 0.2 {
+            return Some((0., 0., 0.));
+        }
+        let i =
+            (compute_lighting(uniforms.lights, &v.position, &n) * self.bands).ceil() / self.bands;
+        Some((v.color.0 * i, v.color.1 * i, v.color.2 * i))
+    }
+}
@@ -0,0 +1,416 @@
+use crate::cli::Sampling;
+use crate::tone_mapping::srgb_decode;
+use image::{DynamicImage, Rgb, Rgb32FImage};
+use serde::Deserialize;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+
+/// Number of octaves of noise summed up into turbulence.
+const OCTAVES: usize = 7;
+
+/// Patterns giving the color of an object at a point on its surface, in place of its constant color.
+/// Checkerboards and images are laid out in the texture coordinates of the surface,
+/// noise and marble are solid textures carved out of the scene's space.
+#[derive(Deserialize)]
+pub enum Texture {
+    /// Squares of the size `size` in texture coordinates, alternating between the object's color and `color`.
+    Checkerboard {
+        color: (f64, f64, f64),
+        #[serde(default = "default_size")]
+        size: f64,
+    },
+    /// A PNG or JPEG image, relative to the scene file, repeated every `size` in texture coordinates.
+    Image {
+        path: PathBuf,
+        #[serde(default = "default_size")]
+        size: f64,
+        /// The loaded image, loaded along with the scene.
+        #[serde(skip)]
+        image: Option<Rgb32FImage>,
+    },
+    /// Perlin noise shading the object's color, with features of the size `scale`.
+    Noise { scale: f64 },
+    /// Veins of `color` through the object's color, `scale` apart and bent by the turbulence.
+    Marble {
+        color: (f64, f64, f64),
+        scale: f64,
+        #[serde(default = "default_turbulence")]
+        turbulence: f64,
+    },
+}
+
+fn default_size() -> f64 {
+    1.
+}
+
+fn default_turbulence() -> f64 {
+    5.
+}
+
+fn default_intensity() -> f64 {
+    1.
+}
+
+impl Texture {
+    /// Checks that the sizes and scales are positive.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Texture::Checkerboard { size, .. } | Texture::Image { size, .. } if *size <= 0. => {
+                Err(format!("texture size must be positive, not {size}"))
+            }
+            Texture::Noise { scale } | Texture::Marble { scale, .. } if *scale <= 0. => {
+                Err(format!("texture scale must be positive, not {scale}"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Loads the image of an image texture, `dir` is the directory its path is relative to.
+    /// With `srgb` the image is decoded to linear light, see `to_rgb32f`.
+    pub fn load(&mut self, dir: &Path, srgb: bool) -> Result<(), Box<dyn Error>> {
+        if let Texture::Image { path, image, .. } = self {
+            let path = dir.join(path);
+            let loaded =
+                image::open(&path).map_err(|e| format!("texture {}: {e}", path.display()))?;
+            *image = Some(to_rgb32f(loaded, srgb));
+        }
+        Ok(())
+    }
+
+    /// Calculate the color of the texture on an object of the color `base`,
+    /// at the point `p` with the texture coordinates `uv`.
+    pub fn color(
+        &self,
+        base: &(f64, f64, f64),
+        p: &(f64, f64, f64),
+        uv: (f64, f64),
+    ) -> (f64, f64, f64) {
+        match self {
+            Texture::Checkerboard { color, size } => {
+                let (u, v) = ((uv.0 / size).floor(), (uv.1 / size).floor());
+                if (u + v).rem_euclid(2.) < 1. {
+                    *base
+                } else {
+                    *color
+                }
+            }
+            Texture::Image {
+                size,
+                image: Some(image),
+                ..
+            } => bilinear(image, uv.0 / size, uv.1 / size),
+            Texture::Image { image: None, .. } => panic!("texture image is not loaded"),
+            Texture::Noise { scale } => {
+                let p = (p.0 / scale, p.1 / scale, p.2 / scale);
+                let k = 0.5 * (1. + perlin(&p));
+                (k * base.0, k * base.1, k * base.2)
+            }
+            Texture::Marble {
+                color,
+                scale,
+                turbulence,
+            } => {
+                let p = (p.0 / scale, p.1 / scale, p.2 / scale);
+                let k = 0.5 * (1. + (2. * PI * p.0 + turbulence * turbulence_at(&p)).sin());
+                (
+                    base.0 + k * (color.0 - base.0),
+                    base.1 + k * (color.1 - base.1),
+                    base.2 + k * (color.2 - base.2),
+                )
+            }
+        }
+    }
+}
+
+/// What the rays that miss every object see, in place of the scene's constant background.
+#[derive(Deserialize)]
+pub enum Environment {
+    /// A sky fading from `horizon` up to `zenith`, with a plain `ground` below the horizon.
+    Gradient {
+        zenith: (f64, f64, f64),
+        horizon: (f64, f64, f64),
+        ground: (f64, f64, f64),
+    },
+    /// An equirectangular image in PNG, JPEG or Radiance HDR format, relative to the scene file.
+    /// The middle of the image lies along +z, `rotation` turns it right by the given angle in degrees.
+    Image {
+        path: PathBuf,
+        /// Brightness multiplier of the image.
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+        #[serde(default)]
+        rotation: f64,
+        /// The loaded image, loaded along with the scene.
+        #[serde(skip)]
+        image: Option<Rgb32FImage>,
+    },
+}
+
+impl Environment {
+    /// Loads the image of an image environment, `dir` is the directory its path is relative to.
+    /// With `srgb` the image is decoded to linear light, see `to_rgb32f`.
+    pub fn load(&mut self, dir: &Path, srgb: bool) -> Result<(), Box<dyn Error>> {
+        if let Environment::Image { path, image, .. } = self {
+            let path = dir.join(path);
+            let loaded =
+                image::open(&path).map_err(|e| format!("environment {}: {e}", path.display()))?;
+            *image = Some(to_rgb32f(loaded, srgb));
+        }
+        Ok(())
+    }
+
+    /// Calculate the color seen in the direction `d`.
+    pub fn color(&self, d: &(f64, f64, f64)) -> (f64, f64, f64) {
+        let len = (d.0 * d.0 + d.1 * d.1 + d.2 * d.2).sqrt();
+        let d = (d.0 / len, d.1 / len, d.2 / len);
+        match self {
+            Environment::Gradient {
+                zenith,
+                horizon,
+                ground,
+            } => {
+                if d.1 < 0. {
+                    return *ground;
+                }
+                (
+                    horizon.0 + d.1 * (zenith.0 - horizon.0),
+                    horizon.1 + d.1 * (zenith.1 - horizon.1),
+                    horizon.2 + d.1 * (zenith.2 - horizon.2),
+                )
+            }
+            Environment::Image {
+                intensity,
+                rotation,
+                image: Some(image),
+                ..
+            } => {
+                let u = 0.5 + (d.0.atan2(d.2) - rotation.to_radians()) / (2. * PI);
+                let v = 0.5 + d.1.clamp(-1., 1.).asin() / PI;
+                let c = bilinear(image, u, v);
+                (intensity * c.0, intensity * c.1, intensity * c.2)
+            }
+            Environment::Image { image: None, .. } => panic!("environment image is not loaded"),
+        }
+    }
+}
+
+/// Converts a loaded image to floating-point colors.
+/// With `srgb`, when the tone mapping encodes the colors with sRGB again, sRGB encoded images are decoded
+/// to linear light; floating-point images such as Radiance HDR are linear already.
+fn to_rgb32f(image: DynamicImage, srgb: bool) -> Rgb32FImage {
+    let linear = matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    let mut image = image.into_rgb32f();
+    if srgb && !linear {
+        image
+            .pixels_mut()
+            .for_each(|px| px.0.iter_mut().for_each(|c| *c = srgb_decode(*c)));
+    }
+    image
+}
+
+/// The texels of a side of `size` texels covered by the texel `i` of the side scaled down to `next` texels,
+/// each with the part of the texel `i` it makes up.
+fn box_weights(i: u32, size: u32, next: u32) -> impl Iterator<Item = (u32, f32)> {
+    let scale = size as f32 / next as f32;
+    let (start, end) = (i as f32 * scale, (i + 1) as f32 * scale);
+    (start.floor() as u32..(end.ceil() as u32).min(size)).map(move |s| {
+        let overlap = (s as f32 + 1.).min(end) - (s as f32).max(start);
+        (s, overlap / scale)
+    })
+}
+
+/// An image texture of the rasterizer together with its mipmaps,
+/// the image scaled down to half its size over and over again.
+pub struct MipMap {
+    /// The image followed by ever smaller versions of it, down to a single texel.
+    levels: Vec<Rgb32FImage>,
+}
+
+impl MipMap {
+    /// Loads a PNG or JPEG image and builds its mipmaps.
+    /// With `srgb` the image is decoded to linear light before it is averaged, see `to_rgb32f`.
+    pub fn load(path: &Path, srgb: bool) -> Result<MipMap, Box<dyn Error>> {
+        let mut levels = vec![to_rgb32f(image::open(path)?, srgb)];
+        loop {
+            let last = levels.last().unwrap();
+            let (w, h) = (last.width(), last.height());
+            if w == 1 && h == 1 {
+                break;
+            }
+            // every texel averages the area of the previous level it covers,
+            // 2 by 2 texels or parts of 3 along an odd side
+            let (nw, nh) = ((w / 2).max(1), (h / 2).max(1));
+            let next = Rgb32FImage::from_fn(nw, nh, |x, y| {
+                let mut sum = [0.; 3];
+                for (sy, wy) in box_weights(y, h, nh) {
+                    for (sx, wx) in box_weights(x, w, nw) {
+                        let px = last.get_pixel(sx, sy);
+                        sum.iter_mut()
+                            .zip(px.0)
+                            .for_each(|(s, c)| *s += wx * wy * c);
+                    }
+                }
+                Rgb(sum)
+            });
+            levels.push(next);
+        }
+        Ok(MipMap { levels })
+    }
+
+    /// Sample the texture at the texture coordinates `uv`, which change by `duv_dx` and `duv_dy`
+    /// from one pixel on the canvas to the next one horizontally and vertically.
+    /// With mipmapping the level is chosen so that a pixel covers about one of its texels.
+    pub fn sample(
+        &self,
+        uv: (f64, f64),
+        duv_dx: (f64, f64),
+        duv_dy: (f64, f64),
+        sampling: Sampling,
+        mipmapping: bool,
+    ) -> (f64, f64, f64) {
+        let lod = if mipmapping {
+            let (w, h) = (
+                self.levels[0].width() as f64,
+                self.levels[0].height() as f64,
+            );
+            // number of texels of the full image along the pixel's sides
+            let footprint = |d: (f64, f64)| (d.0 * w).hypot(d.1 * h);
+            footprint(duv_dx)
+                .max(footprint(duv_dy))
+                .log2()
+                .max(0.)
+                .min((self.levels.len() - 1) as f64)
+        } else {
+            0.
+        };
+        match sampling {
+            Sampling::Nearest => nearest(&self.levels[lod.round() as usize], uv.0, uv.1),
+            Sampling::Bilinear => {
+                // blend the two closest levels
+                let level = lod.floor() as usize;
+                let t = lod - level as f64;
+                let c0 = bilinear(&self.levels[level], uv.0, uv.1);
+                if t == 0. {
+                    return c0;
+                }
+                let c1 = bilinear(&self.levels[level + 1], uv.0, uv.1);
+                (
+                    c0.0 + t * (c1.0 - c0.0),
+                    c0.1 + t * (c1.1 - c0.1),
+                    c0.2 + t * (c1.2 - c0.2),
+                )
+            }
+        }
+    }
+}
+
+/// Sample the image at the texture coordinates (u, v) by taking the closest texel.
+/// The image is laid out as in `bilinear`.
+fn nearest(image: &Rgb32FImage, u: f64, v: f64) -> (f64, f64, f64) {
+    let (w, h) = (image.width() as i64, image.height() as i64);
+    let x = ((u * w as f64).floor() as i64).rem_euclid(w);
+    let y = (((1. - v) * h as f64).floor() as i64).rem_euclid(h);
+    let px = image.get_pixel(x as u32, y as u32);
+    (px[0] as f64, px[1] as f64, px[2] as f64)
+}
+
+/// Sample the image at the texture coordinates (u, v) with bilinear filtering.
+/// The image spans the unit square with v pointing up, and repeats outside of it.
+fn bilinear(image: &Rgb32FImage, u: f64, v: f64) -> (f64, f64, f64) {
+    let (w, h) = (image.width() as i64, image.height() as i64);
+    // pixel centers lie at half coordinates
+    let x = u * w as f64 - 0.5;
+    let y = (1. - v) * h as f64 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let texel = |dx: i64, dy: i64| {
+        let px = image.get_pixel(
+            (x0 as i64 + dx).rem_euclid(w) as u32,
+            (y0 as i64 + dy).rem_euclid(h) as u32,
+        );
+        (px[0] as f64, px[1] as f64, px[2] as f64)
+    };
+    let (c00, c10, c01, c11) = (texel(0, 0), texel(1, 0), texel(0, 1), texel(1, 1));
+    let mix = |a: f64, b: f64, c: f64, d: f64| {
+        (1. - fy) * ((1. - fx) * a + fx * b) + fy * ((1. - fx) * c + fx * d)
+    };
+    (
+        mix(c00.0, c10.0, c01.0, c11.0),
+        mix(c00.1, c10.1, c01.1, c11.1),
+        mix(c00.2, c10.2, c01.2, c11.2),
+    )
+}
+
+/// Hashes a point of the integer lattice into a pseudo-random number, the same in every run.
+pub fn hash(x: i64, y: i64, z: i64) -> u64 {
+    let mut h = (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+        ^ (z as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+    h ^= h >> 32;
+    h = h.wrapping_mul(0xd6e8_feb8_6659_fd93);
+    h ^ (h >> 32)
+}
+
+/// Dot product of the offset (x, y, z) with one of the 12 gradients pointing to the edges of a cube,
+/// chosen by the hash as in Ken Perlin's improved noise.
+fn gradient(hash: u64, x: f64, y: f64, z: f64) -> f64 {
+    match hash % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+/// Perlin noise at the point, varying smoothly between about -1 and 1 with features of size 1.
+fn perlin(p: &(f64, f64, f64)) -> f64 {
+    let (xi, yi, zi) = (p.0.floor(), p.1.floor(), p.2.floor());
+    let (x, y, z) = (p.0 - xi, p.1 - yi, p.2 - zi);
+    let fade = |t: f64| t * t * t * (t * (t * 6. - 15.) + 10.);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+    let lerp = |t: f64, a: f64, b: f64| a + t * (b - a);
+    let g = |dx: i64, dy: i64, dz: i64| {
+        gradient(
+            hash(xi as i64 + dx, yi as i64 + dy, zi as i64 + dz),
+            x - dx as f64,
+            y - dy as f64,
+            z - dz as f64,
+        )
+    };
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, g(0, 0, 0), g(1, 0, 0)),
+            lerp(u, g(0, 1, 0), g(1, 1, 0)),
+        ),
+        lerp(
+            v,
+            lerp(u, g(0, 0, 1), g(1, 0, 1)),
+            lerp(u, g(0, 1, 1), g(1, 1, 1)),
+        ),
+    )
+}
+
+/// Sum of the absolute values of noise at ever finer scales.
+fn turbulence_at(p: &(f64, f64, f64)) -> f64 {
+    let mut sum = 0.;
+    let mut f = 1.;
+    for _ in 0..OCTAVES {
+        sum += perlin(&(f * p.0, f * p.1, f * p.2)).abs() / f;
+        f *= 2.;
+    }
+    sum
+}
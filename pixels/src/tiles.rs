@@ -0,0 +1,110 @@
+use crate::canvas::Canvas;
+use rayon::prelude::*;
+use std::time::{Duration, Instant};
+
+/// Width and height of a tile in pixels.
+const TILE_SIZE: usize = 32;
+
+/// A rectangular part of the canvas, in pixels from the top left corner.
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+/// Splits the canvas into tiles, ordered from the center of the canvas outwards.
+fn split(width: usize, height: usize) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(TILE_SIZE) {
+        for x in (0..width).step_by(TILE_SIZE) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: TILE_SIZE.min(width - x),
+                height: TILE_SIZE.min(height - y),
+            });
+        }
+    }
+    // the center is usually the most interesting part
+    let distance = |tile: &Tile| {
+        let dx = (2 * tile.x + tile.width) as f64 - width as f64;
+        let dy = (2 * tile.y + tile.height) as f64 - height as f64;
+        dx * dx + dy * dy
+    };
+    tiles.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+    tiles
+}
+
+/// Draws the canvas tile by tile, drawing a batch of tiles in parallel at a time.
+/// With a time budget, only the batches that fit in it are drawn in one frame and the rest in the following ones,
+/// so that slow renders show their progress in the window. Without it, every frame is drawn whole.
+#[derive(Default)]
+pub struct TileRenderer {
+    budget: Option<Duration>,
+    width: usize,
+    height: usize,
+    tiles: Vec<Tile>,
+    /// Number of tiles of the current frame drawn so far.
+    done: usize,
+}
+
+impl TileRenderer {
+    pub fn new(budget: Option<Duration>) -> TileRenderer {
+        TileRenderer {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    /// Starts drawing the frame anew, e.g. when the camera moves.
+    pub fn reset(&mut self) {
+        self.done = 0;
+    }
+
+    /// Draws the next tiles onto the canvas, `shade` gives the color of the pixel in the given column and row.
+    /// Pixels are shaded independently of each other, so the result does not depend on the order of the tiles.
+    pub fn draw<Shade>(&mut self, canvas: &mut Canvas, shade: Shade)
+    where
+        Shade: Fn(usize, usize) -> [f32; 3] + Sync,
+    {
+        let (width, height) = (canvas.width, canvas.height);
+        if (self.width, self.height) != (width, height) {
+            self.width = width;
+            self.height = height;
+            self.tiles = split(width, height);
+            self.done = 0;
+        }
+        if self.budget.is_none() {
+            self.done = 0;
+        }
+
+        let start = Instant::now();
+        let batch = 2 * rayon::current_num_threads();
+        while self.done < self.tiles.len() {
+            let tiles = &self.tiles[self.done..(self.done + batch).min(self.tiles.len())];
+            let colors = tiles
+                .par_iter()
+                .map(|tile| {
+                    let mut colors = Vec::with_capacity(tile.width * tile.height);
+                    for y in tile.y..tile.y + tile.height {
+                        for x in tile.x..tile.x + tile.width {
+                            colors.push(shade(x, y));
+                        }
+                    }
+                    colors
+                })
+                .collect::<Vec<_>>();
+            for (tile, colors) in tiles.iter().zip(colors) {
+                for (row, colors) in colors.chunks_exact(tile.width).enumerate() {
+                    let start = (tile.y + row) * width + tile.x;
+                    canvas.frame[start..start + colors.len()].copy_from_slice(colors);
+                }
+            }
+            self.done += tiles.len();
+            if self.budget.is_some_and(|budget| start.elapsed() > budget) {
+                break;
+            }
+        }
+    }
+}
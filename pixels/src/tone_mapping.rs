@@ -0,0 +1,54 @@
+use crate::cli::ToneMap;
+use rayon::prelude::*;
+
+/// Turns the floating-point colors of the canvas into the 8-bit RGBA frame that is displayed or saved.
+#[derive(Clone, Copy)]
+pub struct ToneMapping {
+    pub operator: ToneMap,
+    /// Exposure in stops.
+    pub exposure: f64,
+    /// Whether to encode the result with the sRGB transfer function.
+    pub srgb: bool,
+}
+
+/// Encodes a linear color channel in [0, 1] with the sRGB transfer function.
+fn srgb_encode(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Decodes an sRGB encoded color channel in [0, 1] to linear light.
+pub fn srgb_decode(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl ToneMapping {
+    /// Maps a single color channel to its 8-bit value.
+    fn map(&self, c: f32) -> u8 {
+        let c = self.operator.apply(c as f64 * 2_f64.powf(self.exposure));
+        let c = if self.srgb { srgb_encode(c) } else { c };
+        (255.99 * c) as u8
+    }
+
+    /// Tone maps the colors into the RGBA frame of the same size.
+    pub fn apply(&self, colors: &[[f32; 3]], frame: &mut [u8]) {
+        frame
+            .par_chunks_exact_mut(4)
+            .zip(colors.par_iter())
+            .for_each(|(pixel, color)| {
+                pixel.copy_from_slice(&[
+                    self.map(color[0]),
+                    self.map(color[1]),
+                    self.map(color[2]),
+                    0xff,
+                ]);
+            });
+    }
+}
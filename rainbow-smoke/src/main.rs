@@ -1,8 +1,10 @@
+use clap::Parser;
 use pixels::{Pixels, SurfaceTexture};
 use rand::prelude::*;
 use rayon::prelude::*;
 // use std::ops::Index;
 use std::collections::HashSet;
+use std::path::PathBuf;
 use std::time::Instant;
 use winit::{
     dpi::LogicalSize,
@@ -15,8 +17,6 @@ use winit_input_helper::WinitInputHelper;
 
 const WIDTH: usize = 500;
 const HEIGHT: usize = 500;
-const START_X: i32 = WIDTH as i32 / 2;
-const START_Y: i32 = HEIGHT as i32 / 2;
 const BITS_PER_CHANNEL: u8 = 6;
 const COLORS_PER_CHANNEL: u8 = 1 << BITS_PER_CHANNEL;
 const AVERAGE: bool = true;
@@ -25,6 +25,51 @@ const AVERAGE: bool = true;
 //     "number of pixels must match number of colors!"
 // );
 
+#[derive(Parser)]
+#[command(about = "Rainbow smoke on top of the pixels frame buffer")]
+struct Cli {
+    /// Width of the canvas in pixels.
+    #[arg(long, default_value_t = WIDTH)]
+    width: usize,
+    /// Height of the canvas in pixels.
+    #[arg(long, default_value_t = HEIGHT)]
+    height: usize,
+    /// Image to take the colors from, evenly spread colors are used if omitted.
+    #[arg(conflicts_with = "avatar")]
+    image: Option<PathBuf>,
+    /// Take the colors from `avatar_1k.jpg`, the image that used to be loaded by default.
+    #[arg(long)]
+    avatar: bool,
+}
+
+/// A frame buffer of RGBA pixels together with its dimensions.
+struct Canvas<'a> {
+    frame: &'a mut [u8],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Canvas<'a> {
+    /// Wraps a frame buffer of `width` by `height` RGBA pixels.
+    fn new(frame: &'a mut [u8], width: usize, height: usize) -> Canvas<'a> {
+        assert_eq!(
+            frame.len(),
+            4 * width * height,
+            "frame size must match the dimensions"
+        );
+        Canvas {
+            frame,
+            width,
+            height,
+        }
+    }
+
+    /// Index of the first byte of the point in the frame.
+    fn index(&self, pos: &Point) -> usize {
+        4 * (pos.y as usize * self.width + pos.x as usize)
+    }
+}
+
 #[derive(Clone, Hash, PartialEq, Eq)]
 struct Point {
     x: i32,
@@ -32,11 +77,13 @@ struct Point {
 }
 
 impl Point {
-    fn get_neighbors(&self) -> PointNeighbors {
+    fn get_neighbors(&self, canvas: &Canvas) -> PointNeighbors {
         PointNeighbors {
             p: self.clone(),
             dx: if self.x == 0 { 0 } else { -1 },
             dy: if self.y == 0 { 0 } else { -1 },
+            width: canvas.width as i32,
+            height: canvas.height as i32,
         }
     }
 }
@@ -45,16 +92,18 @@ struct PointNeighbors {
     p: Point,
     dx: i32,
     dy: i32,
+    width: i32,
+    height: i32,
 }
 
 impl Iterator for PointNeighbors {
     type Item = Point;
     fn next(&mut self) -> Option<Self::Item> {
-        if self.dx > 1 || self.p.x + self.dx >= WIDTH as i32 {
+        if self.dx > 1 || self.p.x + self.dx >= self.width {
             self.dy += 1;
             self.dx = if self.p.x == 0 { 0 } else { -1 };
         }
-        if self.dy > 1 || self.p.y + self.dy >= HEIGHT as i32 {
+        if self.dy > 1 || self.p.y + self.dy >= self.height {
             None
         } else {
             let pdx = self.dx;
@@ -90,32 +139,31 @@ impl Color {
         };
         ret
     }
-    fn from_pixel(pixels: &[u8], pos: &Point) -> Option<Color> {
-        let pos = 4 * (pos.y as usize * WIDTH + pos.x as usize);
-        if pixels[pos + 3] > 0 {
+    fn from_pixel(canvas: &Canvas, pos: &Point) -> Option<Color> {
+        let pos = canvas.index(pos);
+        if canvas.frame[pos + 3] > 0 {
             Some(Color {
-                r: pixels[pos],
-                g: pixels[pos + 1],
-                b: pixels[pos + 2],
+                r: canvas.frame[pos],
+                g: canvas.frame[pos + 1],
+                b: canvas.frame[pos + 2],
             })
         } else {
             None
         }
     }
-    fn fill_point(&self, pixels: &mut [u8], pos: &Point) {
-        let pos = 4 * (pos.y as usize * WIDTH + pos.x as usize);
-        pixels[pos] = self.r;
-        pixels[pos + 1] = self.g;
-        pixels[pos + 2] = self.b;
-        pixels[pos + 3] = 0xff;
+    fn fill_point(&self, canvas: &mut Canvas, pos: &Point) {
+        let pos = canvas.index(pos);
+        canvas.frame[pos] = self.r;
+        canvas.frame[pos + 1] = self.g;
+        canvas.frame[pos + 2] = self.b;
+        canvas.frame[pos + 3] = 0xff;
     }
 }
 
-fn calc_diff(pixels: &[u8], xy: &Point, c: &Color, average: bool) -> i32 {
-    let mut diffs = vec![];
-    diffs.reserve(8);
-    for nxy in xy.get_neighbors() {
-        let nc = Color::from_pixel(pixels, &nxy);
+fn calc_diff(canvas: &Canvas, xy: &Point, c: &Color, average: bool) -> i32 {
+    let mut diffs = Vec::with_capacity(8);
+    for nxy in xy.get_neighbors(canvas) {
+        let nc = Color::from_pixel(canvas, &nxy);
         if let Some(nc) = nc {
             diffs.push(nc.col_diff(c));
         }
@@ -140,64 +188,68 @@ struct RainbowSmoke {
 }
 
 impl RainbowSmoke {
-    fn new() -> RainbowSmoke {
-        let mut colors = vec![];
-        colors.reserve(
+    fn new(width: usize, height: usize) -> RainbowSmoke {
+        let mut colors = Vec::with_capacity(
             COLORS_PER_CHANNEL as usize * COLORS_PER_CHANNEL as usize * COLORS_PER_CHANNEL as usize,
         );
         for r in 0..COLORS_PER_CHANNEL {
             for g in 0..COLORS_PER_CHANNEL {
                 for b in 0..COLORS_PER_CHANNEL {
                     colors.push(Color {
-                        r: (r as u16 * 0xff as u16 / (COLORS_PER_CHANNEL - 1) as u16) as u8,
-                        g: (g as u16 * 0xff as u16 / (COLORS_PER_CHANNEL - 1) as u16) as u8,
-                        b: (b as u16 * 0xff as u16 / (COLORS_PER_CHANNEL - 1) as u16) as u8,
+                        r: (r as u16 * 0xff_u16 / (COLORS_PER_CHANNEL - 1) as u16) as u8,
+                        g: (g as u16 * 0xff_u16 / (COLORS_PER_CHANNEL - 1) as u16) as u8,
+                        b: (b as u16 * 0xff_u16 / (COLORS_PER_CHANNEL - 1) as u16) as u8,
                     });
                 }
             }
         }
         colors.shuffle(&mut rand::thread_rng());
+        let (start_x, start_y) = (width as i32 / 2, height as i32 / 2);
         let mut available = HashSet::new();
         available.insert(Point {
-            x: START_X,
-            y: START_Y,
+            x: start_x,
+            y: start_y,
         });
         available.insert(Point {
-            x: START_X / 2,
-            y: START_Y / 2,
+            x: start_x / 2,
+            y: start_y / 2,
         });
         available.insert(Point {
-            x: 3 * START_X / 2,
-            y: 3 * START_Y / 2,
+            x: 3 * start_x / 2,
+            y: 3 * start_y / 2,
         });
         available.insert(Point {
             x: 0,
-            y: HEIGHT as i32 - 1,
+            y: height as i32 - 1,
         });
         available.insert(Point {
-            x: WIDTH as i32 - 1,
+            x: width as i32 - 1,
             y: 0,
         });
-        RainbowSmoke { colors, available, average: false }
+        RainbowSmoke::from(colors, available)
     }
 
     fn from(colors: Vec<Color>, available: HashSet<Point>) -> RainbowSmoke {
-        RainbowSmoke { colors, available, average: false }
+        RainbowSmoke {
+            colors,
+            available,
+            average: false,
+        }
     }
 
-    fn next_pixel(&mut self, pixels: &mut [u8]) -> bool {
+    fn next_pixel(&mut self, canvas: &mut Canvas) -> bool {
         if let Some(c) = self.colors.pop() {
             if let Some(best_xy) = self
                 .available
                 .par_iter()
-                .map(|p| (p, calc_diff(pixels, p, &c, self.average)))
+                .map(|p| (p, calc_diff(canvas, p, &c, self.average)))
                 .min_by(|(_, a), (_, b)| a.cmp(b))
                 .map(|(a, _)| a.clone())
             {
-                c.fill_point(pixels, &best_xy);
+                c.fill_point(canvas, &best_xy);
                 self.available.remove(&best_xy);
-                for nxy in best_xy.get_neighbors() {
-                    match Color::from_pixel(pixels, &nxy) {
+                for nxy in best_xy.get_neighbors(canvas) {
+                    match Color::from_pixel(canvas, &nxy) {
                         Some(_) => {}
                         None => {
                             self.available.insert(nxy);
@@ -215,13 +267,16 @@ impl RainbowSmoke {
 }
 
 fn main() {
+    let cli = Cli::parse();
+    let (width, height) = (cli.width, cli.height);
+
     // main event loop and inpu helper
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
 
     // window that contains the framebuffer
     let window = {
-        let size = LogicalSize::new(WIDTH as u32, HEIGHT as u32);
+        let size = LogicalSize::new(width as u32, height as u32);
         WindowBuilder::new()
             .with_title("Hello Pixels")
             .with_inner_size(size)
@@ -235,37 +290,46 @@ fn main() {
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture).unwrap()
+        Pixels::new(width as u32, height as u32, surface_texture).unwrap()
     };
 
     // frame timer
     let mut prev_time = Instant::now();
 
-    // load image
-    let img = image::io::Reader::open("avatar_1k.jpg")
-        .unwrap()
-        .decode()
-        .unwrap()
-        .into_rgb8()
-        .into_vec();
-    let mut img = img
-        .chunks(3)
-        .map(|c| Color {
-            r: c[0],
-            g: c[1],
-            b: c[2],
-        })
-        .collect::<Vec<_>>();
-    img.shuffle(&mut rand::thread_rng());
-
-    // rainbow smoke generator
-    let mut rainbow_smoke = RainbowSmoke::from(
-        img,
-        HashSet::from([Point {
-            x: WIDTH as i32 / 2,
-            y: HEIGHT as i32 / 2,
-        }]),
-    );
+    // rainbow smoke generator, using colors of the image if given
+    let image = match (&cli.image, cli.avatar) {
+        (Some(path), _) => Some(path.clone()),
+        (None, true) => Some(PathBuf::from("avatar_1k.jpg")),
+        (None, false) => None,
+    };
+    let mut rainbow_smoke = match &image {
+        Some(path) => {
+            // load image
+            let img = image::io::Reader::open(path)
+                .unwrap()
+                .decode()
+                .unwrap()
+                .into_rgb8()
+                .into_vec();
+            let mut img = img
+                .chunks(3)
+                .map(|c| Color {
+                    r: c[0],
+                    g: c[1],
+                    b: c[2],
+                })
+                .collect::<Vec<_>>();
+            img.shuffle(&mut rand::thread_rng());
+            RainbowSmoke::from(
+                img,
+                HashSet::from([Point {
+                    x: width as i32 / 2,
+                    y: height as i32 / 2,
+                }]),
+            )
+        }
+        None => RainbowSmoke::new(width, height),
+    };
     rainbow_smoke.average = AVERAGE;
     pixels.get_frame().fill(0x0);
 
@@ -273,10 +337,10 @@ fn main() {
         // draw a new frame
         if let Event::RedrawRequested(_) = event {
             // draw the content
-            let framebuffer = pixels.get_frame();
+            let mut canvas = Canvas::new(pixels.get_frame(), width, height);
             print!("Candidates: {}, ", rainbow_smoke.available.len());
             for _ in 0..25 {
-                rainbow_smoke.next_pixel(framebuffer);
+                rainbow_smoke.next_pixel(&mut canvas);
             }
 
             // display drawing time and frames per second